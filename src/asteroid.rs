@@ -1,101 +1,60 @@
 use std::time::Duration;
 
 use bevy::prelude::*;
-use bevy_hanabi::{ParticleEffect, ParticleEffectBundle};
 use bevy_rand::{global::GlobalEntropy, prelude::Entropy, traits::ForkableRng};
-use lightyear::prelude::is_server;
-use lightyear::prelude::server::Replicate;
+use bevy_spatial::SpatialAccess;
 use rand::prelude::Rng;
 use rand_distr::Distribution;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    CircleCollider, CleanupOnGameOver, CollisionEvent, GameState, LARGE_ASTEROID_RADIUS, RngType,
-    SMALL_ASTEROID_RADIUS, Velocity, WINDOW_HEIGHT, WINDOW_WIDTH, WrapTimeout,
-    particles::CleanupAfterTimeout, player::ScoreMarker,
+    CircleCollider, CleanupOnGameOver, GameState, NNTree, RngType, Velocity, WINDOW_HEIGHT,
+    WINDOW_WIDTH, WrapTimeout,
+    game_mode::{GameMode, MatchClock},
+    player::{OnPlayerDamage, PlayerId, ScoreMarker},
+    rules::{AsteroidPhysics, GameRules},
+    simulation::ResolvedHits,
 };
 
+/// Draws the asteroids of the simulation, see [`crate::simulation::SimulationPlugin`].
 pub struct AsteroidPlugin;
 
+/// Delay between asteroid spawns outside of survival.
+const SPAWN_INTERVAL: Duration = Duration::from_secs(1);
+/// Survival halves the spawn interval after this long, thirds it after twice as long and so on.
+const SURVIVAL_RAMP: Duration = Duration::from_secs(30);
+const MIN_SPAWN_INTERVAL: Duration = Duration::from_millis(100);
+
 impl Plugin for AsteroidPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .add_systems(
-                Update,
-                (
-                    spawn_asteroid.run_if(is_server),
-                    handle_grace,
-                    resolve_asteroid_collisions,
-                )
-                    .run_if(in_state(GameState::Playing)),
+        app.add_systems(
+            Update,
+            (
+                setup.run_if(resource_changed::<GameRules>),
+                attach_asteroid_sprite.run_if(in_state(GameState::Playing)),
             )
-            .add_observer(divide_on_collision);
+                .chain(),
+        );
     }
 }
 
 #[derive(Component)]
 pub struct AsteroidSpawner {
     timer: Timer,
+    pub tuning: AsteroidTuning,
+    /// Splits draw from their own generator so that hits do not change the spawned field.
+    pub splits: Entropy<RngType>,
+}
+
+#[derive(Resource)]
+pub struct AsteroidSprite {
     material: Handle<ColorMaterial>,
     small_mesh: Handle<Mesh>,
     large_mesh: Handle<Mesh>,
 }
 
-fn setup(
-    mut cmd: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut global: GlobalEntropy<RngType>,
-) {
-    let small_asteroid_mesh = meshes.add(Circle::new(SMALL_ASTEROID_RADIUS));
-    let large_asteroid_mesh = meshes.add(Circle::new(LARGE_ASTEROID_RADIUS));
-    let asteroid_mat = materials.add(Color::linear_rgb(256.0, 0.0, 0.0));
-    cmd.spawn((
-        AsteroidSpawner::new(small_asteroid_mesh, large_asteroid_mesh, asteroid_mat),
-        global.fork_rng(),
-    ));
-}
-
-#[derive(Component, PartialEq, Serialize, Deserialize, Debug, Clone)]
-pub struct LargeAsteroid;
-
-impl AsteroidSpawner {
-    fn new(
-        small_mesh: Handle<Mesh>,
-        large_mesh: Handle<Mesh>,
-        material: Handle<ColorMaterial>,
-    ) -> Self {
-        Self {
-            timer: Timer::new(Duration::from_secs(1), TimerMode::Once),
-            small_mesh,
-            large_mesh,
-            material,
-        }
-    }
-
-    fn asteroid(
-        &self,
-        pos: Transform,
-        is_large: bool,
-        velocity: Velocity,
-        grace: bool,
-    ) -> impl Bundle {
-        (
-            Transform::from_translation(pos.translation),
-            velocity,
-            self.asteroid_client(is_large),
-            WrapTimeout(5),
-            if grace {
-                PostSpawnGrace::default()
-            } else {
-                Default::default()
-            },
-            CleanupOnGameOver,
-            Replicate::default(),
-        )
-    }
-
-    pub fn asteroid_client(&self, is_large: bool) -> impl Bundle {
+impl AsteroidSprite {
+    pub fn asteroid(&self, is_large: bool) -> impl Bundle {
         (
             Mesh2d(if is_large {
                 self.large_mesh.clone()
@@ -105,22 +64,109 @@ impl AsteroidSpawner {
             MeshMaterial2d(self.material.clone()),
         )
     }
+}
 
-    fn velocity(&self, rng: &mut Entropy<RngType>) -> Velocity {
-        let velocity = rand_distr::Uniform::new(-3.0, 3.0);
+/// Destroyed asteroid, triggered for effects.
+#[derive(Event)]
+pub struct AsteroidDestroyed(pub Transform);
+
+/// Adjustments applied on top of the regular asteroid field, e.g. by daily challenges.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AsteroidTuning {
+    pub speed: f32,
+    pub large_chance: f64,
+    /// Multiplies how often asteroids spawn.
+    pub spawn_rate: f32,
+}
+
+impl AsteroidTuning {
+    fn velocity(&self, rng: &mut Entropy<RngType>, rules: &GameRules) -> Velocity {
+        let max = rules.asteroid_speed * self.speed;
+        let velocity = rand_distr::Uniform::new(-max, max);
         Velocity {
             x: velocity.sample(&mut *rng),
             y: velocity.sample(&mut *rng),
         }
     }
+}
+
+impl Default for AsteroidTuning {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            large_chance: 0.2,
+            spawn_rate: 1.0,
+        }
+    }
+}
+
+/// Rebuilds the meshes whenever the rules change the asteroid sizes.
+fn setup(
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    rules: Res<GameRules>,
+) {
+    cmd.insert_resource(AsteroidSprite {
+        small_mesh: meshes.add(Circle::new(rules.small_asteroid_radius)),
+        large_mesh: meshes.add(Circle::new(rules.large_asteroid_radius)),
+        material: materials.add(Color::linear_rgb(256.0, 0.0, 0.0)),
+    });
+}
+
+pub fn spawn_asteroid_spawner(mut cmd: Commands, mut global: GlobalEntropy<RngType>) {
+    let mut field = global.fork_rng();
+    cmd.spawn((AsteroidSpawner::new(field.fork_rng()), field));
+}
+
+/// Asteroids spawned by the simulation or replicated from the server get drawn.
+fn attach_asteroid_sprite(
+    mut cmd: Commands,
+    asteroids: Query<
+        (Entity, Has<LargeAsteroid>),
+        (
+            Added<Velocity>,
+            Without<PlayerId>,
+            Without<ScoreMarker>,
+            Without<Mesh2d>,
+        ),
+    >,
+    sprite: Res<AsteroidSprite>,
+) {
+    for (entity, is_large) in &asteroids {
+        cmd.entity(entity).insert(sprite.asteroid(is_large));
+    }
+}
+
+/// Gameplay components of an asteroid, without its size.
+pub fn asteroid(position: Vec3, velocity: Velocity, rules: &GameRules) -> impl Bundle {
+    (
+        Transform::from_translation(position),
+        velocity,
+        WrapTimeout(rules.asteroid_wraps),
+        CleanupOnGameOver,
+    )
+}
+
+#[derive(Component, PartialEq, Serialize, Deserialize, Debug, Clone)]
+pub struct LargeAsteroid;
+
+impl AsteroidSpawner {
+    fn new(splits: Entropy<RngType>) -> Self {
+        Self {
+            timer: Timer::new(SPAWN_INTERVAL, TimerMode::Once),
+            tuning: AsteroidTuning::default(),
+            splits,
+        }
+    }
 
-    fn spawn(&self, cmd: &mut Commands, rng: &mut Entropy<RngType>) {
+    fn spawn(&self, cmd: &mut Commands, rng: &mut Entropy<RngType>, rules: &GameRules) {
         let screen_distr_x = rand_distr::Uniform::new(0.0, WINDOW_WIDTH);
         let screen_distr_y = rand_distr::Uniform::new(0.0, WINDOW_HEIGHT);
         let axis = rng.gen_bool(0.5);
-        let is_large = rng.gen_bool(0.2);
-        let mut asteroid = cmd.spawn(self.asteroid(
-            Transform::from_xyz(
+        let is_large = rng.gen_bool(self.tuning.large_chance);
+        let mut spawned = cmd.spawn(asteroid(
+            Vec3::new(
                 if axis {
                     screen_distr_x.sample(&mut *rng)
                 } else {
@@ -133,97 +179,201 @@ impl AsteroidSpawner {
                 },
                 0.0,
             ),
-            is_large,
-            self.velocity(&mut *rng),
-            false,
+            self.tuning.velocity(rng, rules),
+            rules,
         ));
         if is_large {
-            asteroid.insert(LargeAsteroid);
+            spawned.insert(LargeAsteroid);
         }
-        asteroid.insert(CircleCollider::new(if is_large {
-            LARGE_ASTEROID_RADIUS
-        } else {
-            SMALL_ASTEROID_RADIUS
-        }));
+        spawned.insert(CircleCollider::asteroid(rules.asteroid_radius(is_large)));
     }
 }
 
 #[derive(Event)]
-struct Divide(Transform);
+pub struct Divide(Transform);
 
 #[derive(Component)]
-struct PostSpawnGrace {
+pub struct PostSpawnGrace {
     timer: Timer,
     collider_radious: f32,
 }
 
-impl Default for PostSpawnGrace {
-    fn default() -> Self {
+impl PostSpawnGrace {
+    pub fn new(collider_radious: f32) -> Self {
         Self {
             timer: Timer::new(Duration::from_secs(1), TimerMode::Once),
-            collider_radious: 20.0,
+            collider_radious,
         }
     }
 }
 
-fn handle_grace(mut e: Query<(Entity, &mut PostSpawnGrace)>, mut cmd: Commands, time: Res<Time>) {
+pub fn handle_grace(
+    mut e: Query<(Entity, &mut PostSpawnGrace)>,
+    mut cmd: Commands,
+    time: Res<Time>,
+) {
     e.iter_mut().for_each(|(e, mut grace)| {
         grace.timer.tick(time.delta());
         if grace.timer.finished() {
             cmd.entity(e).remove::<PostSpawnGrace>();
             cmd.entity(e)
-                .insert(CircleCollider::new(grace.collider_radious));
+                .insert(CircleCollider::asteroid(grace.collider_radious));
         }
     });
 }
 
-fn divide_on_collision(
+pub fn divide_on_collision(
     trigger: Trigger<Divide>,
     mut cmd: Commands,
-    mut spawner: Query<(&AsteroidSpawner, &mut Entropy<RngType>)>,
+    mut spawner: Single<&mut AsteroidSpawner>,
+    rules: Res<GameRules>,
 ) {
-    let (spawner, mut rng) = spawner.single_mut();
-    cmd.spawn(spawner.asteroid(trigger.0, false, spawner.velocity(&mut rng), true));
-    cmd.spawn(spawner.asteroid(trigger.0, false, spawner.velocity(&mut rng), true));
+    let spawner = &mut **spawner;
+    let position = trigger.0.translation;
+    for _ in 0..2 {
+        cmd.spawn((
+            asteroid(
+                position,
+                spawner.tuning.velocity(&mut spawner.splits, &rules),
+                &rules,
+            ),
+            PostSpawnGrace::new(rules.small_asteroid_radius),
+        ));
+    }
 }
 
-fn spawn_asteroid(
+pub fn ramp_spawn_rate(
+    mut spawner: Single<&mut AsteroidSpawner>,
+    mode: Res<GameMode>,
+    clock: Res<MatchClock>,
+) {
+    let ramp = match *mode {
+        GameMode::Survival => 1.0 + clock.elapsed.as_secs_f32() / SURVIVAL_RAMP.as_secs_f32(),
+        _ => 1.0,
+    };
+    let interval = SPAWN_INTERVAL
+        .div_f32(ramp * spawner.tuning.spawn_rate)
+        .max(MIN_SPAWN_INTERVAL);
+    if spawner.timer.duration() != interval {
+        spawner.timer.set_duration(interval);
+    }
+}
+
+pub fn spawn_asteroid(
     mut cmd: Commands,
     time: Res<Time>,
     mut spawner: Query<(&mut AsteroidSpawner, &mut Entropy<RngType>)>,
+    rules: Res<GameRules>,
 ) {
     let (mut spawner, mut rng) = spawner.single_mut();
     spawner.timer.tick(time.delta());
 
     if spawner.timer.finished() {
-        spawner.spawn(&mut cmd, &mut rng);
+        spawner.spawn(&mut cmd, &mut rng, &rules);
         spawner.timer.reset();
     }
 }
 
-fn resolve_asteroid_collisions(
-    mut e: EventReader<CollisionEvent>,
+/// Removes a hit asteroid, splitting large ones.
+pub fn destroy_asteroid(
+    cmd: &mut Commands,
+    asteroid: Entity,
+    transform: &Transform,
+    is_large: bool,
+) {
+    cmd.entity(asteroid).try_despawn();
+    cmd.trigger(AsteroidDestroyed(*transform));
+    if is_large {
+        cmd.trigger(Divide(*transform));
+    }
+}
+
+/// Bounces asteroids off each other and off ships with momentum conserved, for
+/// [`AsteroidPhysics::Elastic`]. Ships ramming an asteroid fast enough break it and get hurt.
+pub fn bounce_asteroids(
     mut cmd: Commands,
-    asteroids: Query<(&WrapTimeout, &Transform, Option<&LargeAsteroid>), Without<ScoreMarker>>,
-    effect: Res<crate::particles::CollisionEffect>,
+    tree: Res<NNTree>,
+    mut bodies: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &CircleCollider,
+            Has<LargeAsteroid>,
+            Has<PlayerId>,
+        ),
+        Without<ScoreMarker>,
+    >,
+    asteroids: Query<
+        Entity,
+        (
+            With<WrapTimeout>,
+            With<CircleCollider>,
+            Without<ScoreMarker>,
+        ),
+    >,
+    rules: Res<GameRules>,
+    mut hits: ResMut<ResolvedHits>,
 ) {
-    for ev in e.read() {
-        if let Ok((_, transform, is_large)) = asteroids.get(ev.0) {
-            cmd.entity(ev.0).try_despawn();
-            cmd.spawn((
-                ParticleEffectBundle {
-                    effect: ParticleEffect::new(effect.0.clone()),
-                    transform: *transform,
-                    ..default()
-                },
-                CleanupAfterTimeout::default(),
-            ));
-            if is_large.is_some() {
-                cmd.trigger(Divide(*transform));
-            }
+    if rules.asteroid_physics != AsteroidPhysics::Elastic {
+        return;
+    }
+    let reach = rules.large_asteroid_radius.max(rules.ship_radius);
+    for asteroid in &asteroids {
+        if hits.contains(asteroid) {
+            continue;
         }
-        if asteroids.get(ev.1).is_ok() {
-            cmd.entity(ev.1).try_despawn();
+        let Ok((transform, _, collider, _, _)) = bodies.get(asteroid) else {
+            continue;
+        };
+        let nearby = tree.within_distance(transform.translation.xy(), collider.radius + reach);
+        for other in nearby.into_iter().filter_map(|(_, it)| it) {
+            // Pairs of asteroids show up from both sides, only the lower entity handles them
+            if other == asteroid
+                || hits.contains(other)
+                || (asteroids.contains(other) && other < asteroid)
+            {
+                continue;
+            }
+            let Ok([a, b]) = bodies.get_many_mut([asteroid, other]) else {
+                continue;
+            };
+            let (mut a_transform, mut a_velocity, a_collider, is_large, _) = a;
+            let (mut b_transform, mut b_velocity, b_collider, _, is_ship) = b;
+            let offset = (a_transform.translation - b_transform.translation).xy();
+            let contact = a_collider.radius + b_collider.radius;
+            let distance = offset.length();
+            if distance >= contact || distance == 0.0 {
+                continue;
+            }
+            let normal = offset / distance;
+            let a_vel = Vec2::new(a_velocity.x, a_velocity.y);
+            let b_vel = Vec2::new(b_velocity.x, b_velocity.y);
+            let closing = (b_vel - a_vel).dot(normal);
+            if closing <= 0.0 {
+                continue;
+            }
+            if is_ship && closing > rules.ram_speed && hits.claim(&[asteroid, other]) {
+                destroy_asteroid(&mut cmd, asteroid, &a_transform, is_large);
+                cmd.trigger_targets(OnPlayerDamage, other);
+                break;
+            }
+            let a_mass = a_collider.mass();
+            let b_mass = b_collider.mass();
+            let total = a_mass + b_mass;
+            let a_vel = a_vel + normal * closing * 2.0 * b_mass / total;
+            let b_vel = b_vel - normal * closing * 2.0 * a_mass / total;
+            *a_velocity = Velocity {
+                x: a_vel.x,
+                y: a_vel.y,
+            };
+            *b_velocity = Velocity {
+                x: b_vel.x,
+                y: b_vel.y,
+            };
+            // Push them apart so they do not stick together, the lighter one moving further
+            let overlap = normal * (contact - distance);
+            a_transform.translation += (overlap * b_mass / total).extend(0.0);
+            b_transform.translation -= (overlap * a_mass / total).extend(0.0);
         }
     }
 }
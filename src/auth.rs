@@ -0,0 +1,207 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use lightyear::connection::netcode::ConnectToken;
+use lightyear::prelude::*;
+
+use crate::shared::{NETCODE_PROTOCOL_ID, ProtocolVersion};
+
+/// Connect tokens are served on the port right after the game port.
+pub const TOKEN_PORT_OFFSET: u16 = 1;
+
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(2);
+const TOKEN_GRANTED: u8 = 1;
+const TOKEN_DENIED: u8 = 0;
+const TOKEN_INCOMPATIBLE: u8 = 2;
+
+/// Options the host picks in the main menu, the password is also used when joining.
+#[derive(Resource, Default)]
+pub struct SecuritySettings {
+    pub require_token: bool,
+    pub password: String,
+    /// Disconnect clients that keep sending input no honest client could.
+    pub kick_cheaters: bool,
+}
+
+pub fn generate_key() -> Key {
+    rand::random::<Key>()
+}
+
+pub fn token_address(game: SocketAddr) -> SocketAddr {
+    SocketAddr::new(game.ip(), game.port().wrapping_add(TOKEN_PORT_OFFSET))
+}
+
+/// Small TCP service run by the host that hands out netcode connect tokens.
+#[derive(Resource)]
+pub struct TokenEndpoint {
+    issued: Mutex<Receiver<u64>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl TokenEndpoint {
+    pub fn start(
+        game: SocketAddr,
+        key: Key,
+        protocol: ProtocolVersion,
+        password: String,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, token_address(game).port()))?;
+        listener.set_nonblocking(true)?;
+        let (sender, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = stop.clone();
+        std::thread::spawn(move || {
+            while !running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // A client that stalls must not hold back the ones after it
+                        let sender = sender.clone();
+                        let password = password.clone();
+                        std::thread::spawn(move || {
+                            let served =
+                                serve_token(stream, game.port(), key, protocol, &password, &sender);
+                            if let Err(e) = served {
+                                warn!("Failed to serve connect token: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => warn!("Token endpoint error: {}", e),
+                }
+            }
+        });
+        Ok(Self {
+            issued: Mutex::new(receiver),
+            stop,
+        })
+    }
+
+    /// Client ids that received a token since the last call.
+    pub fn drain_issued(&self) -> Vec<u64> {
+        self.issued
+            .lock()
+            .map(|receiver| receiver.try_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for TokenEndpoint {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn serve_token(
+    stream: TcpStream,
+    game_port: u16,
+    key: Key,
+    protocol: ProtocolVersion,
+    password: &str,
+    issued: &Sender<u64>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TOKEN_TIMEOUT))?;
+    // Clients reach the game server through the same interface they used for the token
+    let server_addr = SocketAddr::new(stream.local_addr()?.ip(), game_port);
+    let mut reader = BufReader::new(stream);
+    let mut version = String::new();
+    reader.read_line(&mut version)?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut stream = reader.into_inner();
+    if version.trim_end_matches(['\r', '\n']).parse() != Ok(protocol.0) {
+        stream.write_all(&[TOKEN_INCOMPATIBLE])?;
+        return stream.write_all(&protocol.0.to_le_bytes());
+    }
+    if line.trim_end_matches(['\r', '\n']) != password {
+        return stream.write_all(&[TOKEN_DENIED]);
+    }
+    let client_id = rand::random::<u64>();
+    let token = ConnectToken::build(server_addr, NETCODE_PROTOCOL_ID, client_id, key)
+        .generate()
+        .map_err(|e| std::io::Error::other(e.to_string()))?
+        .try_into_bytes()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    stream.write_all(&[TOKEN_GRANTED])?;
+    stream.write_all(&token)?;
+    let _ = issued.send(ClientId::Netcode(client_id).to_bits());
+    Ok(())
+}
+
+pub enum TokenResponse {
+    Granted(ConnectToken),
+    Denied,
+    /// The host runs a different protocol, carries the host's [`ProtocolVersion`].
+    Incompatible(u64),
+    /// The host does not run a token endpoint, so it accepts unauthenticated clients.
+    Unavailable,
+}
+
+/// Blocking request for a connect token, meant to run on the IO task pool.
+pub fn request_token(
+    game: SocketAddr,
+    protocol: ProtocolVersion,
+    password: &str,
+) -> Result<TokenResponse, String> {
+    let mut stream = match TcpStream::connect_timeout(&token_address(game), TOKEN_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            return Ok(TokenResponse::Unavailable);
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let io = |e: std::io::Error| e.to_string();
+    stream.set_read_timeout(Some(TOKEN_TIMEOUT)).map_err(io)?;
+    stream
+        .write_all(format!("{}\n{}\n", protocol.0, password).as_bytes())
+        .map_err(io)?;
+    let mut status = [0u8; 1];
+    stream.read_exact(&mut status).map_err(io)?;
+    match status[0] {
+        TOKEN_GRANTED => {}
+        TOKEN_INCOMPATIBLE => {
+            let mut version = [0u8; 8];
+            stream.read_exact(&mut version).map_err(io)?;
+            return Ok(TokenResponse::Incompatible(u64::from_le_bytes(version)));
+        }
+        _ => return Ok(TokenResponse::Denied),
+    }
+    let mut token = Vec::new();
+    stream.read_to_end(&mut token).map_err(io)?;
+    ConnectToken::try_from_bytes(&token)
+        .map(TokenResponse::Granted)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+    use std::time::{Duration, Instant};
+
+    use crate::auth::{TokenEndpoint, TokenResponse, generate_key, request_token, token_address};
+    use crate::shared::ProtocolVersion;
+
+    #[test]
+    fn stalled_clients_do_not_hold_back_others() {
+        let game = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 47310);
+        let protocol = ProtocolVersion(7);
+        let endpoint = TokenEndpoint::start(game, generate_key(), protocol, "pw".into()).unwrap();
+        // Connects and never sends a request
+        let _stalled = TcpStream::connect(token_address(game)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+
+        let asked = Instant::now();
+        let response = request_token(game, protocol, "pw").unwrap();
+        assert!(matches!(response, TokenResponse::Granted(_)));
+        assert!(asked.elapsed() < Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(endpoint.drain_issued().len(), 1);
+    }
+}
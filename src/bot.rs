@@ -0,0 +1,278 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
+use lightyear::prelude::is_server;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+use crate::game_mode::GameMode;
+use crate::player::{Downed, Eliminated, PlayerAction, PlayerId, ScoreMarker};
+use crate::server::{ConnectedPlayers, Rematch, StartGame};
+use crate::shared::MatchResults;
+use crate::simulation::{ShipAction, apply_ship_actions, authority};
+use crate::{GameState, HostGame, NNTree, WrapTimeout};
+
+pub struct BotPlugin;
+
+/// How far bots look for asteroids and ships.
+const SENSE_RADIUS: f32 = 600.0;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            drive_bots
+                .before(apply_ship_actions)
+                .run_if(in_state(GameState::Playing).and(authority())),
+        )
+        .add_systems(
+            Update,
+            (
+                soak_host.run_if(in_state(GameState::MainMenu)),
+                soak_start.run_if(in_state(GameState::Lobby).and(is_server)),
+                soak_rematch.run_if(in_state(GameState::GameOver).and(is_server)),
+            )
+                .run_if(resource_exists::<SoakTest>),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, EnumIter)]
+pub enum BotDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl BotDifficulty {
+    pub fn name(&self) -> String {
+        match self {
+            BotDifficulty::Easy => t!("bot.easy").to_string(),
+            BotDifficulty::Normal => t!("bot.normal").to_string(),
+            BotDifficulty::Hard => t!("bot.hard").to_string(),
+        }
+    }
+
+    /// Time between two decisions.
+    fn reaction(&self) -> Duration {
+        match self {
+            BotDifficulty::Easy => Duration::from_millis(400),
+            BotDifficulty::Normal => Duration::from_millis(200),
+            BotDifficulty::Hard => Duration::from_millis(50),
+        }
+    }
+
+    /// Largest angle in radians between heading and target at which the bot fires.
+    fn aim_tolerance(&self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.35,
+            BotDifficulty::Normal => 0.2,
+            BotDifficulty::Hard => 0.08,
+        }
+    }
+
+    /// Distance at which an asteroid is avoided rather than shot.
+    fn avoid_radius(&self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 80.0,
+            BotDifficulty::Normal => 120.0,
+            BotDifficulty::Hard => 160.0,
+        }
+    }
+}
+
+/// Computer controlled ship, driven on the server.
+#[derive(Component)]
+pub struct Bot {
+    difficulty: BotDifficulty,
+    action: PlayerAction,
+    decision: Timer,
+}
+
+impl Bot {
+    pub fn new(difficulty: BotDifficulty) -> Self {
+        Self {
+            difficulty,
+            action: PlayerAction::None,
+            decision: Timer::new(difficulty.reaction(), TimerMode::Repeating),
+        }
+    }
+}
+
+/// Picks the action bringing the ship away from `threat` or onto `target`.
+pub fn decide(
+    ship: &Transform,
+    threat: Option<Vec2>,
+    target: Option<Vec2>,
+    difficulty: BotDifficulty,
+) -> PlayerAction {
+    let position = ship.translation.xy();
+    let heading = (ship.rotation * Vec3::Y).xy();
+    // Flee by turning away from the threat and thrusting
+    let (goal, aimed) = match (threat, target) {
+        (Some(threat), _) => (position - threat, PlayerAction::Forward),
+        (None, Some(target)) => (target - position, PlayerAction::Shoot),
+        (None, None) => return PlayerAction::None,
+    };
+    let angle = heading.angle_to(goal);
+    let tolerance = match aimed {
+        PlayerAction::Forward => PI / 4.0,
+        _ => difficulty.aim_tolerance(),
+    };
+    if angle.abs() <= tolerance {
+        aimed
+    } else if angle > 0.0 {
+        // Positive rotate values turn clockwise
+        PlayerAction::Rotate(-1)
+    } else {
+        PlayerAction::Rotate(1)
+    }
+}
+
+fn drive_bots(
+    mut bots: Query<(&PlayerId, &Transform, &mut Bot), (Without<Eliminated>, Without<Downed>)>,
+    asteroids: Query<(), (With<WrapTimeout>, Without<ScoreMarker>)>,
+    ships: Query<&PlayerId, (Without<Eliminated>, Without<Downed>)>,
+    tree: Res<NNTree>,
+    mode: Res<GameMode>,
+    time: Res<Time>,
+    mut actions: EventWriter<ShipAction>,
+) {
+    for (id, transform, mut bot) in &mut bots {
+        bot.decision.tick(time.delta());
+        if bot.decision.just_finished() {
+            let position = transform.translation.xy();
+            let mut nearby = tree.within_distance(position, SENSE_RADIUS);
+            nearby.sort_by(|a, b| a.0.distance(position).total_cmp(&b.0.distance(position)));
+            let nearest = |filter: &dyn Fn(Entity) -> bool| {
+                nearby
+                    .iter()
+                    .find(|(_, entity)| entity.is_some_and(filter))
+                    .map(|(pos, _)| *pos)
+            };
+            let asteroid = nearest(&|entity| asteroids.contains(entity));
+            let threat =
+                asteroid.filter(|it| it.distance(position) < bot.difficulty.avoid_radius());
+            let target = match *mode {
+                GameMode::Versus => {
+                    nearest(&|entity| ships.get(entity).is_ok_and(|other| other != id))
+                }
+                _ => asteroid,
+            };
+            bot.action = decide(transform, threat, target, bot.difficulty);
+        }
+        actions.send(ShipAction {
+            player: id.0,
+            input: bot.action.clone().into(),
+        });
+        // Holding the trigger would fire every tick
+        if bot.action == PlayerAction::Shoot {
+            bot.action = PlayerAction::None;
+        }
+    }
+}
+
+/// Hosts matches between bots over and over, started with `--soak <bots>`.
+#[derive(Resource)]
+pub struct SoakTest {
+    bots: usize,
+    matches: u32,
+    /// State the soak test last moved on from, it stays there until the state changes.
+    acted_in: Option<GameState>,
+}
+
+impl SoakTest {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        args.find(|arg| arg == "--soak")?;
+        let bots = args.next().and_then(|it| it.parse().ok()).unwrap_or(4);
+        Some(Self {
+            bots,
+            matches: 0,
+            acted_in: None,
+        })
+    }
+
+    /// Whether the soak test has yet to move on from `state`, true once per visit.
+    fn act_in(&mut self, state: GameState) -> bool {
+        let first = self.acted_in.as_ref() != Some(&state);
+        self.acted_in = Some(state);
+        first
+    }
+}
+
+fn soak_host(mut cmd: Commands, mut soak: ResMut<SoakTest>) {
+    if soak.act_in(GameState::MainMenu) {
+        cmd.trigger(HostGame::default());
+    }
+}
+
+fn soak_start(
+    mut cmd: Commands,
+    mut soak: ResMut<SoakTest>,
+    mut players: ResMut<ConnectedPlayers>,
+) {
+    if !soak.act_in(GameState::Lobby) {
+        return;
+    }
+    for _ in players.bots()..soak.bots {
+        players.add_bot(BotDifficulty::Hard);
+    }
+    cmd.trigger(StartGame);
+}
+
+fn soak_rematch(mut cmd: Commands, mut soak: ResMut<SoakTest>, results: Option<Res<MatchResults>>) {
+    if !soak.act_in(GameState::GameOver) {
+        return;
+    }
+    soak.matches += 1;
+    let best = results
+        .and_then(|it| it.players.first().map(|it| it.score))
+        .unwrap_or_default();
+    info!(
+        "Soak test match {} finished, best score {}",
+        soak.matches, best
+    );
+    cmd.trigger(Rematch);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::GameState;
+    use crate::bot::{BotDifficulty, SoakTest, decide};
+    use crate::player::PlayerAction;
+
+    #[test]
+    fn bot_turns_toward_target_and_shoots() {
+        // Ships face up by default
+        let ship = Transform::from_xyz(0.0, 0.0, 0.0);
+        let difficulty = BotDifficulty::Hard;
+        assert_eq!(
+            decide(&ship, None, Some(Vec2::new(0.0, 100.0)), difficulty),
+            PlayerAction::Shoot
+        );
+        assert_eq!(
+            decide(&ship, None, Some(Vec2::new(100.0, 0.0)), difficulty),
+            PlayerAction::Rotate(1)
+        );
+        assert_eq!(
+            decide(&ship, Some(Vec2::new(0.0, -50.0)), None, difficulty),
+            PlayerAction::Forward
+        );
+    }
+
+    #[test]
+    fn soak_test_moves_on_once_per_state() {
+        let mut soak = SoakTest::from_args(["--soak".to_string()].into_iter()).unwrap();
+        assert!(soak.act_in(GameState::Lobby));
+        assert!(!soak.act_in(GameState::Lobby));
+        assert!(soak.act_in(GameState::GameOver));
+        // Back in the lobby for the rematch
+        assert!(soak.act_in(GameState::Lobby));
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use egui::Align2;
+use rust_i18n::t;
+
+use crate::GameState;
+
+pub struct ChatPlugin;
+
+pub const MAX_CHAT_LENGTH: usize = 200;
+pub const CHAT_LOG_SIZE: usize = 100;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatLog>()
+            .init_resource::<ChatInput>()
+            .add_event::<SendChat>()
+            .add_systems(OnEnter(GameState::MainMenu), clear_chat)
+            .add_systems(
+                Update,
+                chat_box.run_if(
+                    in_state(GameState::Lobby)
+                        .or(in_state(GameState::Playing))
+                        .or(in_state(GameState::GameOver)),
+                ),
+            );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChatEntry {
+    pub sender: String,
+    pub text: String,
+}
+
+#[derive(Resource, Default)]
+pub struct ChatLog {
+    pub entries: Vec<ChatEntry>,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, sender: String, text: String) {
+        self.entries.push(ChatEntry { sender, text });
+        if self.entries.len() > CHAT_LOG_SIZE {
+            self.entries.remove(0);
+        }
+    }
+}
+
+/// State of the local chat input line.
+#[derive(Resource, Default)]
+pub struct ChatInput {
+    open: bool,
+    text: String,
+}
+
+/// Text typed by the local player, sent by either the client or the host.
+#[derive(Event)]
+pub struct SendChat(pub String);
+
+/// Run condition that is true while the chat input captures the keyboard.
+pub fn is_typing(input: Res<ChatInput>) -> bool {
+    input.open
+}
+
+/// Trims the message and cuts it to [`MAX_CHAT_LENGTH`] characters.
+pub fn sanitize(text: &str) -> String {
+    text.trim().chars().take(MAX_CHAT_LENGTH).collect()
+}
+
+fn clear_chat(mut log: ResMut<ChatLog>, mut input: ResMut<ChatInput>) {
+    log.entries.clear();
+    *input = ChatInput::default();
+}
+
+fn chat_box(
+    mut ctx: EguiContexts,
+    key: Res<ButtonInput<KeyCode>>,
+    log: Res<ChatLog>,
+    mut input: ResMut<ChatInput>,
+    mut ev_send: EventWriter<SendChat>,
+) {
+    let mut focus = false;
+    if !input.open && key.just_pressed(KeyCode::Enter) {
+        input.open = true;
+        focus = true;
+    }
+    // Nothing to draw on without the egui plugin, e.g. with the ui disabled
+    let Some(egui_ctx) = ctx.try_ctx_mut() else {
+        return;
+    };
+    let rect = egui_ctx.input(|i: &egui::InputState| i.screen_rect());
+    egui::Window::new(t!("chat"))
+        .pivot(Align2::LEFT_BOTTOM)
+        .current_pos(egui::Pos2::new(10.0, rect.max.y - 10.0))
+        .default_width(300.0)
+        .show(egui_ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in &log.entries {
+                        ui.label(format!("{}: {}", entry.sender, entry.text));
+                    }
+                });
+            if !input.open {
+                ui.label(t!("chat.hint"));
+                return;
+            }
+            let response = ui.text_edit_singleline(&mut input.text);
+            if focus {
+                response.request_focus();
+            }
+            if response.lost_focus() {
+                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    let text = sanitize(&input.text);
+                    if !text.is_empty() {
+                        ev_send.send(SendChat(text));
+                    }
+                }
+                input.text.clear();
+                input.open = false;
+            }
+        });
+}
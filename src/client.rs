@@ -1,7 +1,9 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use bevy::prelude::*;
+use bevy::tasks::{IoTaskPool, Task, block_on, futures_lite::future};
 use client::{ClientCommands, ClientTransport, IoConfig, NetConfig, NetcodeConfig};
+use lightyear::client::events::ConnectEvent;
 use lightyear::prelude::*;
 use lightyear::shared::events::components::{EntitySpawnEvent, MessageEvent};
 use lightyear::{
@@ -10,26 +12,46 @@ use lightyear::{
 };
 use rust_i18n::t;
 
-use crate::asteroid::{AsteroidSpawner, LargeAsteroid};
-use crate::player::{PlayerId, PlayerSpawner, ProjectileSprite, ScoreMarker};
+use crate::asteroid::LargeAsteroid;
+use crate::auth::{SecuritySettings, TokenResponse, request_token};
+use crate::chat::{ChatLog, SendChat};
+use crate::game_mode::TeamPool;
+use crate::network_debug::{NetworkSettings, count_messages, count_replication, receive_probes};
+use crate::player::{
+    Downed, Eliminated, Owner, PlayerId, PlayerName, PlayerSpawner, PlayerStats, ScoreMarker,
+};
+use crate::rules::GameRules;
+use crate::shared::{
+    ChatChannel, ChatMessage, DefaultChannel, HandshakeMessage, KillFeedMessage, MatchResults,
+    NETCODE_PROTOCOL_ID, PlayerInfoMessage, ProbeChannel, ProbeMessage, ProtocolVersion,
+    RejectMessage, RejectReason, ReturnToLobbyMessage, WelcomeMessage,
+};
+use crate::spectator::{LocalPlayer, Spectating};
+use crate::ui::Rejection;
+use crate::weapon::{WeaponKind, Weapons};
 use crate::{
-    CircleCollider, CleanupOnGameStart, GameState, JoinGame, LARGE_ASTEROID_RADIUS, SERVER_ADDR,
-    SMALL_ASTEROID_RADIUS, ServerAddress, Velocity, shared,
+    CircleCollider, CleanupOnGameStart, GameState, JoinGame, Lives, Nickname, Score, ServerAddress,
+    Velocity, shared,
 };
 
 pub struct ClientPlugin;
 
 pub const CLIENT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
 
-fn net_config(address: SocketAddr, id: u64) -> NetConfig {
-    let auth = Authentication::Manual {
+/// Hosts without a token endpoint use the default key, the protocol is checked by the handshake.
+fn manual_auth(address: SocketAddr, id: u64) -> Authentication {
+    Authentication::Manual {
         server_addr: address,
         client_id: id,
         private_key: Key::default(),
-        protocol_id: 0,
-    };
+        protocol_id: NETCODE_PROTOCOL_ID,
+    }
+}
+
+fn net_config(auth: Authentication, conditioner: Option<LinkConditionerConfig>) -> NetConfig {
     let io = IoConfig {
         transport: ClientTransport::UdpSocket(CLIENT_ADDR),
+        conditioner,
         ..default()
     };
     NetConfig::Netcode {
@@ -41,10 +63,10 @@ fn net_config(address: SocketAddr, id: u64) -> NetConfig {
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
-        let id = rand::random::<u64>();
+        // The real address is only known when joining
         let config = ClientConfig {
-            shared: shared::shared_config(),
-            net: net_config(SERVER_ADDR, id),
+            shared: shared::shared_config(Mode::HostServer),
+            net: net_config(Authentication::None, None),
             ..default()
         };
         app.add_plugins(ClientPlugins::new(config));
@@ -53,11 +75,53 @@ impl Plugin for ClientPlugin {
             .add_systems(
                 Update,
                 (
-                    update_client_config.run_if(in_state(GameState::MainMenu)),
                     wait_for_start.run_if(in_state(GameState::Lobby)),
+                    wait_for_results.run_if(in_state(GameState::Playing)),
+                    wait_for_lobby.run_if(in_state(GameState::GameOver)),
+                    poll_connect_token.run_if(resource_exists::<PendingToken>),
+                    send_handshake,
+                    handle_rejection,
+                    handle_welcome,
+                    send_chat.run_if(not(is_server)),
+                    receive_chat,
+                    receive_kill_feed.run_if(not(is_server)),
+                    receive_rules.run_if(not(is_server)),
                     on_asteroid_spawn,
-                    on_bullet_spawn,
-                    on_player_spawn.run_if(in_state(GameState::Playing)),
+                    on_player_spawn,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        count_messages::<DefaultChannel, RejectMessage>,
+                        count_messages::<DefaultChannel, WelcomeMessage>,
+                        count_messages::<DefaultChannel, shared::StartGameMessage>,
+                        count_messages::<DefaultChannel, ReturnToLobbyMessage>,
+                        count_messages::<DefaultChannel, KillFeedMessage>,
+                        count_messages::<DefaultChannel, MatchResults>,
+                        count_messages::<DefaultChannel, GameRules>,
+                        count_messages::<ChatChannel, ChatMessage>,
+                        count_messages::<ProbeChannel, ProbeMessage>,
+                    ),
+                    (
+                        count_replication::<Transform>,
+                        count_replication::<Velocity>,
+                        count_replication::<LargeAsteroid>,
+                        count_replication::<PlayerId>,
+                        count_replication::<ScoreMarker>,
+                        count_replication::<Owner>,
+                        count_replication::<PlayerName>,
+                        count_replication::<Score>,
+                        count_replication::<Lives>,
+                        count_replication::<PlayerStats>,
+                        count_replication::<Eliminated>,
+                        count_replication::<Downed>,
+                        count_replication::<TeamPool>,
+                        count_replication::<WeaponKind>,
+                        count_replication::<Weapons>,
+                    ),
+                    receive_probes,
                 ),
             );
     }
@@ -65,13 +129,116 @@ impl Plugin for ClientPlugin {
 
 fn wait_for_start(
     mut events: EventReader<MessageEvent<shared::StartGameMessage>>,
+    mut cmd: Commands,
     mut state: ResMut<NextState<GameState>>,
 ) {
-    for _ in events.read() {
+    for event in events.read() {
+        let start = event.message();
+        cmd.insert_resource(start.mode);
+        cmd.insert_resource(start.settings.clone());
         state.set(GameState::Playing);
     }
 }
 
+fn receive_kill_feed(mut events: EventReader<MessageEvent<KillFeedMessage>>, mut cmd: Commands) {
+    for event in events.read() {
+        cmd.trigger(event.message().0.clone());
+    }
+}
+
+fn receive_rules(mut events: EventReader<MessageEvent<GameRules>>, mut cmd: Commands) {
+    for event in events.read() {
+        cmd.insert_resource(event.message().clone());
+    }
+}
+
+fn wait_for_results(
+    mut events: EventReader<MessageEvent<MatchResults>>,
+    mut cmd: Commands,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for event in events.read() {
+        cmd.insert_resource(event.message().clone());
+        state.set(GameState::GameOver);
+    }
+}
+
+fn wait_for_lobby(
+    mut events: EventReader<MessageEvent<ReturnToLobbyMessage>>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for _ in events.read() {
+        state.set(GameState::Lobby);
+    }
+}
+
+fn send_handshake(
+    mut events: EventReader<ConnectEvent>,
+    mut connection: ResMut<client::ConnectionManager>,
+    nickname: Res<Nickname>,
+    protocol: Res<ProtocolVersion>,
+) {
+    for _ in events.read() {
+        connection
+            .send_message::<DefaultChannel, HandshakeMessage>(&HandshakeMessage {
+                version: protocol.0,
+            })
+            .unwrap_or_else(|e| {
+                error!("Failed to send handshake: {}", e);
+            });
+        connection
+            .send_message::<DefaultChannel, PlayerInfoMessage>(&PlayerInfoMessage {
+                name: nickname.0.clone(),
+            })
+            .unwrap_or_else(|e| {
+                error!("Failed to send player info: {}", e);
+            });
+    }
+}
+
+fn handle_welcome(mut events: EventReader<MessageEvent<WelcomeMessage>>, mut cmd: Commands) {
+    for event in events.read() {
+        let welcome = event.message();
+        cmd.insert_resource(LocalPlayer(welcome.id));
+        if welcome.spectating {
+            cmd.insert_resource(Spectating::default());
+        }
+    }
+}
+
+fn handle_rejection(
+    mut events: EventReader<MessageEvent<RejectMessage>>,
+    mut cmd: Commands,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for event in events.read() {
+        warn!("Connection rejected: {:?}", event.message().0);
+        cmd.insert_resource(Rejection(event.message().0.clone()));
+        cmd.disconnect_client();
+        state.set(GameState::MainMenu);
+    }
+}
+
+fn send_chat(mut events: EventReader<SendChat>, mut connection: ResMut<client::ConnectionManager>) {
+    for event in events.read() {
+        connection
+            .send_message::<ChatChannel, ChatMessage>(&ChatMessage {
+                sender: String::new(),
+                text: event.0.clone(),
+            })
+            .unwrap_or_else(|e| {
+                error!("Failed to send chat message: {}", e);
+            });
+    }
+}
+
+fn receive_chat(mut events: EventReader<MessageEvent<ChatMessage>>, mut log: ResMut<ChatLog>) {
+    for event in events.read() {
+        let message = event.message();
+        log.push(message.sender.clone(), message.text.clone());
+    }
+}
+
 fn on_join_lobby(mut cmd: Commands) {
     cmd.spawn((
         Node {
@@ -99,19 +266,13 @@ fn on_asteroid_spawn(
         (Without<PlayerId>, Without<ScoreMarker>),
     >,
     mut cmd: Commands,
-    spawner: Single<&AsteroidSpawner>,
+    rules: Res<GameRules>,
 ) {
     for event in events.read() {
         if let Ok(entity) = asteroids.get(event.entity()) {
             let is_large = entity.2.is_some();
-            cmd.entity(event.entity()).insert((
-                spawner.asteroid_client(is_large),
-                CircleCollider::new(if is_large {
-                    LARGE_ASTEROID_RADIUS
-                } else {
-                    SMALL_ASTEROID_RADIUS
-                }),
-            ));
+            cmd.entity(event.entity())
+                .insert(CircleCollider::asteroid(rules.asteroid_radius(is_large)));
         }
     }
 }
@@ -130,41 +291,69 @@ fn on_player_spawn(
     }
 }
 
-fn on_bullet_spawn(
-    mut events: EventReader<EntitySpawnEvent>,
-    asteroids: Query<(&Transform, &Velocity), With<ScoreMarker>>,
-    mut cmd: Commands,
-    material: Res<ProjectileSprite>,
-) {
-    for event in events.read() {
-        if let Ok(_entity) = asteroids.get(event.entity()) {
-            cmd.entity(event.entity()).insert((
-                Mesh2d(material.1.clone()),
-                MeshMaterial2d(material.0.clone()),
-            ));
-        }
-    }
+/// Connect token request in flight, the client connects once it resolves.
+#[derive(Resource)]
+struct PendingToken {
+    address: SocketAddr,
+    task: Task<Result<TokenResponse, String>>,
 }
 
 fn on_join_game(
     _trigger: Trigger<JoinGame>,
     mut cmd: Commands,
+    address: Res<ServerAddress>,
+    security: Res<SecuritySettings>,
+    protocol: Res<ProtocolVersion>,
+) {
+    // The address may have been picked from the server browser this frame
+    let address = SocketAddr::new(
+        address
+            .ip
+            .parse()
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        address.port,
+    );
+    let password = security.password.clone();
+    let protocol = *protocol;
+    cmd.remove_resource::<Rejection>();
+    cmd.insert_resource(PendingToken {
+        address,
+        task: IoTaskPool::get().spawn(async move { request_token(address, protocol, &password) }),
+    });
+}
+
+fn poll_connect_token(
+    mut cmd: Commands,
+    mut pending: ResMut<PendingToken>,
     mut state: ResMut<NextState<GameState>>,
+    mut client_config: ResMut<ClientConfig>,
+    network: Res<NetworkSettings>,
+    protocol: Res<ProtocolVersion>,
 ) {
+    let Some(response) = block_on(future::poll_once(&mut pending.task)) else {
+        return;
+    };
+    cmd.remove_resource::<PendingToken>();
+    let auth = match response {
+        Ok(TokenResponse::Granted(token)) => Authentication::Token(token),
+        Ok(TokenResponse::Unavailable) => manual_auth(pending.address, rand::random::<u64>()),
+        Ok(TokenResponse::Incompatible(server)) => {
+            cmd.insert_resource(Rejection(RejectReason::IncompatibleVersion {
+                server,
+                client: protocol.0,
+            }));
+            return;
+        }
+        Ok(TokenResponse::Denied) => {
+            cmd.insert_resource(Rejection(RejectReason::WrongPassword));
+            return;
+        }
+        Err(e) => {
+            cmd.insert_resource(Rejection(RejectReason::TokenUnavailable(e)));
+            return;
+        }
+    };
+    client_config.net = net_config(auth, network.conditioner());
     cmd.connect_client();
     state.set(GameState::Lobby);
 }
-
-fn update_client_config(mut client_config: ResMut<ClientConfig>, address: Res<ServerAddress>) {
-    if address.is_changed() {
-        let address = SocketAddr::new(
-            address
-                .ip
-                .parse()
-                .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
-            address.port,
-        );
-        let id = rand::random::<u64>();
-        client_config.net = net_config(address, id)
-    }
-}
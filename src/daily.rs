@@ -0,0 +1,425 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy_rand::prelude::Entropy;
+use bevy_rand::traits::ForkableRng;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::is_server;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rust_i18n::t;
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::asteroid::{AsteroidSpawner, AsteroidTuning};
+use crate::player::{Eliminated, Player, PlayerAction, PlayerId, PlayerSpawner, ShipInput};
+use crate::rules::GameRules;
+use crate::server::StartGame;
+use crate::shared::MatchResults;
+use crate::simulation::{apply_ship_actions, apply_ship_dynamics, steer_ship};
+use crate::spectator::LocalPlayer;
+use crate::{
+    AngularVelocity, CleanupOnGameOver, GameState, Lives, RngType, Velocity, WINDOW_HEIGHT,
+    WINDOW_WIDTH,
+};
+
+pub struct DailyPlugin;
+
+/// Results and best replays are stored per date in this directory.
+const DAILY_DIR: &str = "daily";
+const DAILY_MODIFIERS: usize = 2;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Daily::today())
+            .init_resource::<DailyRecorder>()
+            .add_systems(Startup, (setup, load_record))
+            .add_systems(OnEnter(GameState::MainMenu), refresh_daily)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (
+                    apply_daily.run_if(is_server),
+                    spawn_ghost.run_if(daily_active),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                save_attempt.run_if(daily_active),
+            )
+            .add_systems(
+                Update,
+                (
+                    start_daily_run.run_if(in_state(GameState::Lobby)),
+                    one_life.run_if(in_state(GameState::Playing).and(is_server)),
+                )
+                    .run_if(daily_active),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    record_replay.before(apply_ship_actions),
+                    play_ghost
+                        .after(apply_ship_actions)
+                        .before(apply_ship_dynamics),
+                )
+                    .run_if(in_state(GameState::Playing).and(daily_active)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, EnumIter)]
+pub enum Modifier {
+    FastAsteroids,
+    OneLife,
+    DenseField,
+    GiantAsteroids,
+}
+
+impl Modifier {
+    pub fn name(&self) -> String {
+        match self {
+            Modifier::FastAsteroids => t!("daily.fast_asteroids").to_string(),
+            Modifier::OneLife => t!("daily.one_life").to_string(),
+            Modifier::DenseField => t!("daily.dense_field").to_string(),
+            Modifier::GiantAsteroids => t!("daily.giant_asteroids").to_string(),
+        }
+    }
+}
+
+/// What was achieved on one date.
+#[derive(Default, Debug, PartialEq)]
+pub struct DailyRecord {
+    pub attempts: u32,
+    pub best: u32,
+    /// Input of the local ship on every tick of the best attempt. The asteroid field is
+    /// seeded, so the input alone plays the attempt back.
+    pub replay: Vec<ShipInput>,
+}
+
+impl DailyRecord {
+    fn path(date: &str) -> PathBuf {
+        PathBuf::from(DAILY_DIR).join(format!("{}.txt", date))
+    }
+
+    /// Runs of the same input are stored as one line, prefixed with their number of ticks.
+    fn encode(&self) -> String {
+        let mut data = format!("{}\t{}\n", self.attempts, self.best);
+        for run in self.replay.chunk_by(|a, b| a == b) {
+            let input = run[0];
+            data += &format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                run.len(),
+                input.thrust,
+                input.rotate,
+                input.shoot as u8,
+                input.switch_weapon as u8
+            );
+        }
+        data
+    }
+
+    fn decode(data: &str) -> Option<Self> {
+        let mut lines = data.lines();
+        let (attempts, best) = lines.next()?.split_once('\t')?;
+        let replay = lines
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let ticks = fields.next()?.parse::<usize>().ok()?;
+                let input = ShipInput {
+                    thrust: fields.next()?.parse().ok()?,
+                    rotate: fields.next()?.parse().ok()?,
+                    shoot: fields.next()? == "1",
+                    switch_weapon: fields.next()? == "1",
+                };
+                Some(std::iter::repeat_n(input, ticks))
+            })
+            .flatten()
+            .collect();
+        Some(Self {
+            attempts: attempts.parse().ok()?,
+            best: best.parse().ok()?,
+            replay,
+        })
+    }
+
+    pub fn load(date: &str) -> Self {
+        std::fs::read_to_string(Self::path(date))
+            .ok()
+            .and_then(|data| Self::decode(&data))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, date: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(DAILY_DIR)?;
+        std::fs::write(Self::path(date), self.encode())
+    }
+}
+
+/// Today's challenge, `active` while the local player is running it.
+#[derive(Resource)]
+pub struct Daily {
+    pub date: String,
+    pub seed: u64,
+    pub modifiers: Vec<Modifier>,
+    pub record: DailyRecord,
+    pub active: bool,
+}
+
+impl Daily {
+    /// Seed and modifiers of the challenge on `date`, the record is loaded by the plugin.
+    pub fn for_date(date: String) -> Self {
+        let seed = date_seed(&date);
+        let mut rng = RngType::seed_from_u64(seed);
+        let mut modifiers = Modifier::iter().collect::<Vec<_>>();
+        modifiers.shuffle(&mut rng);
+        modifiers.truncate(DAILY_MODIFIERS);
+        Self {
+            date,
+            seed,
+            modifiers,
+            record: DailyRecord::default(),
+            active: false,
+        }
+    }
+
+    pub fn today() -> Self {
+        let days = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |it| it.as_secs() / 86400);
+        let (year, month, day) = date_from_days(days as i64);
+        Self::for_date(format!("{:04}-{:02}-{:02}", year, month, day))
+    }
+
+    pub fn tuning(&self) -> AsteroidTuning {
+        let mut tuning = AsteroidTuning::default();
+        for modifier in &self.modifiers {
+            match modifier {
+                Modifier::FastAsteroids => tuning.speed *= 2.0,
+                Modifier::DenseField => tuning.spawn_rate *= 2.0,
+                Modifier::GiantAsteroids => tuning.large_chance = 0.6,
+                Modifier::OneLife => (),
+            }
+        }
+        tuning
+    }
+}
+
+/// FNV-1a of the date, so every player gets the same seed on the same day.
+fn date_seed(date: &str) -> u64 {
+    date.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) UTC date.
+fn date_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months are counted from March so that the leap day comes last
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month as u32, day as u32)
+}
+
+pub fn daily_active(daily: Res<Daily>) -> bool {
+    daily.active
+}
+
+#[derive(Resource)]
+struct GhostSprite(Handle<ColorMaterial>);
+
+/// Flies the input of the best attempt of the day again, without touching anything.
+#[derive(Component)]
+struct Ghost {
+    replay: Vec<ShipInput>,
+    tick: usize,
+}
+
+#[derive(Resource, Default)]
+struct DailyRecorder {
+    replay: Vec<ShipInput>,
+}
+
+fn setup(mut cmd: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    cmd.insert_resource(GhostSprite(
+        materials.add(Color::srgba(1.0, 1.0, 1.0, 0.25)),
+    ));
+}
+
+fn load_record(mut daily: ResMut<Daily>) {
+    daily.record = DailyRecord::load(&daily.date);
+}
+
+/// Ends the run, and moves on to a new challenge once the day is over.
+fn refresh_daily(mut daily: ResMut<Daily>) {
+    daily.active = false;
+    let today = Daily::today();
+    if today.date != daily.date {
+        *daily = today;
+        daily.record = DailyRecord::load(&daily.date);
+    }
+}
+
+fn start_daily_run(mut cmd: Commands) {
+    cmd.trigger(StartGame);
+}
+
+/// Seeds the asteroid field and applies the modifiers, or restores the regular field.
+fn apply_daily(daily: Res<Daily>, spawner: Single<(&mut AsteroidSpawner, &mut Entropy<RngType>)>) {
+    let (mut spawner, mut rng) = spawner.into_inner();
+    if !daily.active {
+        spawner.tuning = AsteroidTuning::default();
+        return;
+    }
+    spawner.tuning = daily.tuning();
+    *rng = Entropy::seed_from_u64(daily.seed);
+    spawner.splits = rng.fork_rng();
+}
+
+fn one_life(daily: Res<Daily>, mut players: Query<&mut Lives, Added<PlayerId>>) {
+    if !daily.modifiers.contains(&Modifier::OneLife) {
+        return;
+    }
+    for mut lives in &mut players {
+        lives.0 = 1;
+    }
+}
+
+fn spawn_ghost(
+    mut cmd: Commands,
+    mut recorder: ResMut<DailyRecorder>,
+    daily: Res<Daily>,
+    spawner: Single<&PlayerSpawner>,
+    sprite: Res<GhostSprite>,
+) {
+    *recorder = DailyRecorder::default();
+    if daily.record.replay.is_empty() {
+        return;
+    }
+    // Starts where the host's ship does
+    cmd.spawn((
+        spawner.player_ghost(sprite.0.clone()),
+        Transform::from_xyz(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0, -1.0),
+        Velocity { x: 0.0, y: 0.0 },
+        AngularVelocity::default(),
+        Ghost {
+            replay: daily.record.replay.clone(),
+            tick: 0,
+        },
+        CleanupOnGameOver,
+    ));
+}
+
+fn record_replay(
+    mut recorder: ResMut<DailyRecorder>,
+    ship: Query<&ActionState<PlayerAction>, (With<Player>, Without<Eliminated>)>,
+) {
+    if let Ok(action_state) = ship.get_single() {
+        recorder
+            .replay
+            .push(action_state.get_pressed().into_iter().collect());
+    }
+}
+
+fn play_ghost(
+    mut cmd: Commands,
+    mut ghosts: Query<(
+        Entity,
+        &mut Ghost,
+        &Transform,
+        &mut Velocity,
+        &mut AngularVelocity,
+    )>,
+    rules: Res<GameRules>,
+    time: Res<Time>,
+) {
+    for (entity, mut ghost, transform, mut velocity, mut spin) in &mut ghosts {
+        // The best attempt ended here
+        let Some(input) = ghost.replay.get(ghost.tick).copied() else {
+            cmd.entity(entity).despawn();
+            continue;
+        };
+        ghost.tick += 1;
+        steer_ship(
+            &input,
+            transform,
+            &mut velocity,
+            &mut spin,
+            &rules,
+            time.delta_secs(),
+        );
+    }
+}
+
+fn save_attempt(
+    mut daily: ResMut<Daily>,
+    mut recorder: ResMut<DailyRecorder>,
+    results: Option<Res<MatchResults>>,
+    local: Option<Res<LocalPlayer>>,
+) {
+    let Some(result) = results.zip(local).and_then(|(results, local)| {
+        results
+            .players
+            .iter()
+            .find(|it| it.id == local.0)
+            .map(|it| it.score)
+    }) else {
+        return;
+    };
+    let daily = &mut *daily;
+    daily.record.attempts += 1;
+    if daily.record.attempts == 1 || result > daily.record.best {
+        daily.record.best = result;
+        daily.record.replay = std::mem::take(&mut recorder.replay);
+    }
+    daily
+        .record
+        .save(&daily.date)
+        .unwrap_or_else(|e| warn!("Failed to save daily challenge: {}", e));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daily::{Daily, DailyRecord, date_from_days};
+    use crate::player::ShipInput;
+
+    #[test]
+    fn dates_from_days() {
+        assert_eq!(date_from_days(0), (1970, 1, 1));
+        assert_eq!(date_from_days(11016), (2000, 2, 29));
+        assert_eq!(date_from_days(20744), (2026, 10, 18));
+    }
+
+    #[test]
+    fn same_date_same_challenge() {
+        let first = Daily::for_date("2026-10-18".to_string());
+        let second = Daily::for_date("2026-10-18".to_string());
+        assert_eq!(first.seed, second.seed);
+        assert_eq!(first.modifiers, second.modifiers);
+    }
+
+    #[test]
+    fn replays_survive_saving() {
+        let turn = ShipInput {
+            rotate: -1,
+            shoot: true,
+            ..Default::default()
+        };
+        let record = DailyRecord {
+            attempts: 3,
+            best: 120,
+            replay: vec![ShipInput::default(), turn, turn, ShipInput::default()],
+        };
+        assert_eq!(DailyRecord::decode(&record.encode()), Some(record));
+    }
+}
@@ -0,0 +1,288 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::GameState;
+
+pub struct DiscoveryPlugin;
+
+/// First port hosts try to listen on for discovery queries.
+pub const DISCOVERY_PORT: u16 = 5100;
+/// Number of consecutive ports probed, so several hosts can run on one machine.
+pub const DISCOVERY_PORT_RANGE: u16 = 4;
+
+const QUERY_HEADER: &str = "ASTEROIDS DISCOVER";
+const ANNOUNCE_HEADER: &str = "ASTEROIDS ANNOUNCE";
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+const SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DiscoveredServers>()
+            .init_resource::<DiscoveryClient>()
+            .add_systems(Update, browse_servers.run_if(in_state(GameState::MainMenu)))
+            .add_systems(OnExit(GameState::MainMenu), stop_browsing);
+    }
+}
+
+/// Information a host sends in reply to a discovery query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Announcement {
+    pub sequence: u32,
+    /// Random id of the hosted game, the same host answers on every address it is reached by.
+    pub server: u64,
+    pub version: u64,
+    pub port: u16,
+    pub players: usize,
+    pub state: String,
+    pub name: String,
+}
+
+impl Announcement {
+    pub fn encode(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            ANNOUNCE_HEADER,
+            self.sequence,
+            self.server,
+            self.version,
+            self.port,
+            self.players,
+            self.state,
+            self.name
+        )
+    }
+
+    pub fn decode(data: &str) -> Option<Self> {
+        let mut lines = data.splitn(8, '\n');
+        if lines.next()? != ANNOUNCE_HEADER {
+            return None;
+        }
+        Some(Self {
+            sequence: lines.next()?.parse().ok()?,
+            server: lines.next()?.parse().ok()?,
+            version: lines.next()?.parse().ok()?,
+            port: lines.next()?.parse().ok()?,
+            players: lines.next()?.parse().ok()?,
+            state: lines.next()?.to_string(),
+            name: lines.next()?.to_string(),
+        })
+    }
+}
+
+pub fn encode_query(sequence: u32) -> String {
+    format!("{}\n{}", QUERY_HEADER, sequence)
+}
+
+/// Returns the sequence number of a discovery query.
+pub fn decode_query(data: &str) -> Option<u32> {
+    let (header, sequence) = data.split_once('\n')?;
+    if header != QUERY_HEADER {
+        return None;
+    }
+    sequence.parse().ok()
+}
+
+/// Binds the first free port of the discovery range for a host to answer on.
+pub fn bind_host_socket() -> Option<UdpSocket> {
+    (DISCOVERY_PORT..DISCOVERY_PORT + DISCOVERY_PORT_RANGE).find_map(|port| {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).ok()?;
+        socket.set_nonblocking(true).ok()?;
+        Some(socket)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub announcement: Announcement,
+    pub ping: Duration,
+    last_seen: Instant,
+}
+
+#[derive(Resource, Default)]
+pub struct DiscoveredServers {
+    pub servers: Vec<DiscoveredServer>,
+}
+
+impl DiscoveredServers {
+    fn update(&mut self, address: SocketAddr, announcement: Announcement, ping: Duration) {
+        let now = Instant::now();
+        // Hosts on this machine answer both the loopback and the broadcast query
+        match self
+            .servers
+            .iter_mut()
+            .find(|it| it.announcement.server == announcement.server)
+        {
+            Some(existing) => {
+                existing.announcement = announcement;
+                existing.ping = ping;
+                existing.last_seen = now;
+            }
+            None => self.servers.push(DiscoveredServer {
+                address,
+                announcement,
+                ping,
+                last_seen: now,
+            }),
+        }
+    }
+
+    fn remove_stale(&mut self) {
+        self.servers
+            .retain(|it| it.last_seen.elapsed() < SERVER_TIMEOUT);
+    }
+}
+
+#[derive(Resource, Default)]
+struct DiscoveryClient {
+    socket: Option<UdpSocket>,
+    sequence: u32,
+    sent_at: Option<Instant>,
+}
+
+impl DiscoveryClient {
+    fn socket(&mut self) -> Option<&UdpSocket> {
+        if self.socket.is_none() {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+            socket.set_nonblocking(true).ok()?;
+            socket.set_broadcast(true).ok()?;
+            self.socket = Some(socket);
+        }
+        self.socket.as_ref()
+    }
+
+    fn query(&mut self) {
+        self.sequence = self.sequence.wrapping_add(1);
+        let query = encode_query(self.sequence);
+        let Some(socket) = self.socket() else {
+            return;
+        };
+        for ip in [Ipv4Addr::LOCALHOST, Ipv4Addr::BROADCAST] {
+            for port in DISCOVERY_PORT..DISCOVERY_PORT + DISCOVERY_PORT_RANGE {
+                // Broadcast is not routable on every network, missing hosts are simply not listed
+                let _ = socket.send_to(query.as_bytes(), SocketAddr::new(IpAddr::V4(ip), port));
+            }
+        }
+        self.sent_at = Some(Instant::now());
+    }
+}
+
+fn browse_servers(mut client: ResMut<DiscoveryClient>, mut servers: ResMut<DiscoveredServers>) {
+    if client
+        .sent_at
+        .is_none_or(|sent| sent.elapsed() >= QUERY_INTERVAL)
+    {
+        client.query();
+    }
+    let (Some(socket), Some(sent_at)) = (client.socket.as_ref(), client.sent_at) else {
+        return;
+    };
+    receive_announcements(socket, client.sequence, sent_at, &mut servers);
+    servers.remove_stale();
+}
+
+/// Lists the hosts that answered query `sequence`, sent at `sent_at`.
+fn receive_announcements(
+    socket: &UdpSocket,
+    sequence: u32,
+    sent_at: Instant,
+    servers: &mut DiscoveredServers,
+) {
+    let mut buf = [0u8; 1024];
+    while let Ok((len, from)) = socket.recv_from(&mut buf) {
+        let Some(announcement) = std::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(Announcement::decode)
+        else {
+            continue;
+        };
+        if announcement.sequence != sequence {
+            continue;
+        }
+        let address = SocketAddr::new(from.ip(), announcement.port);
+        servers.update(address, announcement, sent_at.elapsed());
+    }
+}
+
+fn stop_browsing(mut client: ResMut<DiscoveryClient>, mut servers: ResMut<DiscoveredServers>) {
+    *client = DiscoveryClient::default();
+    servers.servers.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    use crate::discovery::{
+        Announcement, DiscoveredServers, decode_query, encode_query, receive_announcements,
+    };
+
+    #[test]
+    fn announcement_over_loopback() {
+        let host = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        client
+            .send_to(encode_query(7).as_bytes(), host.local_addr().unwrap())
+            .unwrap();
+        let mut buf = [0u8; 1024];
+        let (len, from) = host.recv_from(&mut buf).unwrap();
+        let sequence = decode_query(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(sequence, 7);
+
+        let announcement = Announcement {
+            sequence,
+            server: 42,
+            version: 1,
+            port: 5000,
+            players: 2,
+            state: "Lobby".to_string(),
+            name: "Host\nwith newline".to_string(),
+        };
+        host.send_to(announcement.encode().as_bytes(), from)
+            .unwrap();
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        let received = Announcement::decode(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(received, announcement);
+    }
+
+    #[test]
+    fn hosts_reached_twice_are_listed_once() {
+        // One host answering twice, like through the loopback and the broadcast query
+        let first = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let second = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = first.local_addr().unwrap().port();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let sent_at = Instant::now();
+        let mut buf = [0u8; 1024];
+        for host in [&first, &second] {
+            client
+                .send_to(encode_query(3).as_bytes(), host.local_addr().unwrap())
+                .unwrap();
+            let (len, from) = host.recv_from(&mut buf).unwrap();
+            let announcement = Announcement {
+                sequence: decode_query(std::str::from_utf8(&buf[..len]).unwrap()).unwrap(),
+                server: 42,
+                version: 1,
+                port,
+                players: 1,
+                state: "Lobby".to_string(),
+                name: "Host".to_string(),
+            };
+            host.send_to(announcement.encode().as_bytes(), from)
+                .unwrap();
+        }
+
+        client
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut servers = DiscoveredServers::default();
+        receive_announcements(&client, 3, sent_at, &mut servers);
+        assert_eq!(servers.servers.len(), 1);
+        assert_eq!(servers.servers[0].announcement.server, 42);
+    }
+}
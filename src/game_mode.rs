@@ -0,0 +1,431 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::player::{
+    Downed, Eliminated, OnPlayerDamage, Owner, PlayerGrace, PlayerId, PlayerName, PlayerStats,
+    ScoreMarker,
+};
+use crate::rules::GameRules;
+use crate::shared::PlayerResult;
+use crate::simulation::{ResolvedHits, authority};
+use crate::weapon::WeaponKind;
+use crate::{
+    CircleCollider, CleanupOnGameOver, GameState, Lives, OnScoreUpdate, ProjectileHitShip, Score,
+};
+
+pub struct GameModePlugin;
+
+const KILL_FEED_DURATION: Duration = Duration::from_secs(5);
+const KILL_FEED_SIZE: usize = 5;
+/// How close a teammate has to stay to a downed ship to revive it.
+const REVIVE_RADIUS: f32 = 120.0;
+const REVIVE_TIME: Duration = Duration::from_secs(3);
+/// Downed ships get back up when a new co-op wave starts, while the team has lives left.
+const WAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameMode>()
+            .init_resource::<MatchSettings>()
+            .init_resource::<MatchClock>()
+            .init_resource::<KillFeed>()
+            .add_systems(OnEnter(GameState::Playing), start_clock)
+            .add_systems(
+                Update,
+                (
+                    tick_clock,
+                    (revive_nearby, revive_on_wave)
+                        .run_if(authority().and(resource_equals(GameMode::Coop))),
+                    track_survival.run_if(authority().and(resource_equals(GameMode::Survival))),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_observer(record_kill);
+    }
+}
+
+#[derive(
+    Resource, Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, EnumIter,
+)]
+pub enum GameMode {
+    /// Cooperative play until every player runs out of lives.
+    #[default]
+    Endless,
+    /// Players shoot each other, asteroids are neutral hazards.
+    Versus,
+    /// The team shares a pool of lives and revives downed teammates.
+    Coop,
+    /// Score as much as possible before the time limit.
+    TimeAttack,
+    /// No score, only the time alive while asteroids spawn ever faster.
+    Survival,
+}
+
+impl GameMode {
+    pub fn name(&self) -> String {
+        match self {
+            GameMode::Endless => t!("mode.endless").to_string(),
+            GameMode::Versus => t!("mode.versus").to_string(),
+            GameMode::Coop => t!("mode.coop").to_string(),
+            GameMode::TimeAttack => t!("mode.time_attack").to_string(),
+            GameMode::Survival => t!("mode.survival").to_string(),
+        }
+    }
+
+    /// Stable identifier used for the high score file.
+    pub fn key(&self) -> &'static str {
+        match self {
+            GameMode::Endless => "endless",
+            GameMode::Versus => "versus",
+            GameMode::Coop => "coop",
+            GameMode::TimeAttack => "time_attack",
+            GameMode::Survival => "survival",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        GameMode::iter().find(|it| it.key() == key)
+    }
+
+    /// Whether the match ends after [`MatchSettings::time_limit`].
+    pub fn has_time_limit(&self) -> bool {
+        matches!(self, GameMode::Versus | GameMode::TimeAttack)
+    }
+
+    /// Whether destroying asteroids and ships awards points.
+    pub fn scores(&self) -> bool {
+        *self != GameMode::Survival
+    }
+
+    /// Value players are ranked and high scores are kept by.
+    pub fn result(&self, player: &PlayerResult) -> u32 {
+        match self {
+            GameMode::Survival => player.stats.survived_secs,
+            _ => player.score,
+        }
+    }
+
+    /// Formats a value returned by [`GameMode::result`].
+    pub fn format_result(&self, value: u32) -> String {
+        match self {
+            GameMode::Survival => format_clock(value as u64),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Ships respawn instead of losing lives.
+    pub fn respawns(&self) -> bool {
+        *self == GameMode::Versus
+    }
+
+    /// Players are given with whether their ship is downed and whether it is eliminated,
+    /// `pool` holds the lives left to a co-op team.
+    pub fn is_over<'a>(
+        &self,
+        settings: &MatchSettings,
+        clock: &MatchClock,
+        pool: Option<&Lives>,
+        mut players: impl Iterator<Item = (&'a Lives, &'a PlayerStats, bool, bool)>,
+    ) -> bool {
+        match self {
+            GameMode::Endless | GameMode::Survival => players.all(|(lives, ..)| lives.0 <= 0),
+            GameMode::TimeAttack => {
+                clock.elapsed >= settings.time_limit() || players.all(|(lives, ..)| lives.0 <= 0)
+            }
+            GameMode::Versus => {
+                clock.elapsed >= settings.time_limit()
+                    || players.any(|(_, stats, ..)| stats.kills >= settings.frag_limit)
+            }
+            // Downed ships can only be revived while the team has lives left
+            GameMode::Coop => {
+                let pool_empty = pool.is_none_or(|pool| pool.0 <= 0);
+                players.all(|(_, _, downed, eliminated)| eliminated || (downed && pool_empty))
+            }
+        }
+    }
+}
+
+/// Limits picked by the host in the lobby.
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MatchSettings {
+    pub frag_limit: u32,
+    pub time_limit_minutes: u32,
+    /// Weapons every ship carries besides the standard gun.
+    pub loadout: Vec<WeaponKind>,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            frag_limit: 10,
+            time_limit_minutes: 5,
+            loadout: Vec::new(),
+        }
+    }
+}
+
+impl MatchSettings {
+    pub fn time_limit(&self) -> Duration {
+        Duration::from_secs(self.time_limit_minutes as u64 * 60)
+    }
+}
+
+/// Time since the current match started.
+#[derive(Resource, Default)]
+pub struct MatchClock {
+    pub elapsed: Duration,
+}
+
+impl MatchClock {
+    /// Current co-op wave, starting at 1.
+    pub fn wave(&self) -> u32 {
+        (self.elapsed.as_secs() / WAVE_INTERVAL.as_secs()) as u32 + 1
+    }
+}
+
+pub fn format_clock(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Shared lives of the co-op team, spawned by the server alongside the ships.
+#[derive(Component, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct TeamPool;
+
+/// Triggered when a player's projectile hits another player's ship.
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+pub struct OnKill {
+    pub killer: String,
+    pub victim: String,
+}
+
+#[derive(Resource, Default)]
+pub struct KillFeed {
+    entries: Vec<(OnKill, Duration)>,
+}
+
+impl KillFeed {
+    pub fn push(&mut self, kill: OnKill, now: Duration) {
+        self.entries.push((kill, now));
+        if self.entries.len() > KILL_FEED_SIZE {
+            self.entries.remove(0);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ModeText;
+
+fn start_clock(mut clock: ResMut<MatchClock>, mut feed: ResMut<KillFeed>) {
+    clock.elapsed = Duration::ZERO;
+    feed.entries.clear();
+}
+
+fn tick_clock(mut clock: ResMut<MatchClock>, time: Res<Time>) {
+    clock.elapsed += time.delta();
+}
+
+/// Mode, limits and kill feed in the top right corner.
+pub fn setup_mode_hud(mut cmd: Commands) {
+    cmd.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Start,
+            justify_content: JustifyContent::End,
+            padding: UiRect {
+                left: Val::Px(0.0),
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                bottom: Val::Px(0.0),
+            },
+            ..default()
+        },
+        CleanupOnGameOver,
+    ))
+    .with_child((
+        Text::new(""),
+        TextLayout::new_with_justify(JustifyText::Right),
+        ModeText,
+    ));
+}
+
+pub fn update_mode_hud(
+    mut text: Single<&mut Text, With<ModeText>>,
+    mode: Res<GameMode>,
+    settings: Res<MatchSettings>,
+    clock: Res<MatchClock>,
+    feed: Res<KillFeed>,
+    time: Res<Time<Real>>,
+    pool: Query<&Lives, With<TeamPool>>,
+    players: Query<(&PlayerName, &Score, Option<&Downed>)>,
+) {
+    let mut lines = vec![mode.name()];
+    match *mode {
+        GameMode::Endless => (),
+        GameMode::Versus | GameMode::TimeAttack => {
+            let remaining = settings
+                .time_limit()
+                .saturating_sub(clock.elapsed)
+                .as_secs();
+            if *mode == GameMode::Versus {
+                lines.push(t!("mode.frag_limit", count = settings.frag_limit).to_string());
+            }
+            lines.push(format_clock(remaining));
+        }
+        GameMode::Survival => {
+            lines.push(
+                t!(
+                    "mode.survived",
+                    time = format_clock(clock.elapsed.as_secs())
+                )
+                .to_string(),
+            );
+        }
+        GameMode::Coop => {
+            let lives = pool.get_single().map_or(0, |it| it.0.max(0) as usize);
+            let score = players.iter().map(|(_, score, _)| score.0).sum::<u32>();
+            lines.push(t!("mode.wave", wave = clock.wave()).to_string());
+            lines.push(t!("mode.team_lives", lives = "X ".repeat(lives)).to_string());
+            lines.push(t!("mode.team_score", score = score).to_string());
+            lines.extend(players.iter().filter_map(|(name, _, downed)| {
+                downed.map(|it| {
+                    t!(
+                        "mode.downed",
+                        name = name.0,
+                        progress = (it.revive_progress * 100.0) as u32
+                    )
+                    .to_string()
+                })
+            }));
+        }
+    }
+    let now = time.elapsed();
+    lines.extend(
+        feed.entries
+            .iter()
+            .filter(|(_, at)| now.saturating_sub(*at) < KILL_FEED_DURATION)
+            .map(|(kill, _)| {
+                t!("killfeed", killer = kill.killer, victim = kill.victim).to_string()
+            }),
+    );
+    text.0 = lines.join("\n");
+}
+
+fn record_kill(trigger: Trigger<OnKill>, mut feed: ResMut<KillFeed>, time: Res<Time<Real>>) {
+    feed.push(trigger.event().clone(), time.elapsed());
+}
+
+/// Versus ships shot by another player lose a life, the shooter scores a kill.
+pub fn resolve_ship_hits(
+    mut e: EventReader<ProjectileHitShip>,
+    mut cmd: Commands,
+    bullets: Query<(&Owner, &WeaponKind), With<ScoreMarker>>,
+    ships: Query<(&PlayerId, &PlayerName), (Without<PlayerGrace>, Without<Eliminated>)>,
+    mut shooters: Query<(Entity, &PlayerId, &PlayerName, &mut PlayerStats)>,
+    rules: Res<GameRules>,
+    mut hits: ResMut<ResolvedHits>,
+) {
+    for &ProjectileHitShip {
+        projectile: bullet,
+        ship,
+    } in e.read()
+    {
+        let (Ok((owner, kind)), Ok((victim, victim_name))) = (bullets.get(bullet), ships.get(ship))
+        else {
+            continue;
+        };
+        if owner.0 == *victim || !hits.claim(&[bullet, ship]) {
+            continue;
+        }
+        if *kind != WeaponKind::Laser {
+            cmd.entity(bullet).try_despawn();
+        }
+        cmd.trigger_targets(OnPlayerDamage, ship);
+        let Some((shooter, _, killer_name, mut stats)) =
+            shooters.iter_mut().find(|(_, id, _, _)| **id == owner.0)
+        else {
+            continue;
+        };
+        stats.kills += 1;
+        cmd.trigger_targets(OnScoreUpdate(rules.kill_score), shooter);
+        cmd.trigger(OnKill {
+            killer: killer_name.0.clone(),
+            victim: victim_name.0.clone(),
+        });
+    }
+}
+
+/// Brings a downed ship back for one life of the team pool, false once the pool is empty.
+fn revive(cmd: &mut Commands, ship: Entity, pool: &mut Lives, rules: &GameRules) -> bool {
+    if pool.0 <= 0 {
+        return false;
+    }
+    pool.0 -= 1;
+    cmd.entity(ship).remove::<Downed>().insert((
+        CircleCollider::ship(rules.ship_radius),
+        PlayerGrace::default(),
+    ));
+    true
+}
+
+/// Teammates staying close to a downed ship for [`REVIVE_TIME`] bring it back.
+fn revive_nearby(
+    mut cmd: Commands,
+    mut downed: Query<(Entity, &Transform, &mut Downed)>,
+    active: Query<&Transform, (With<PlayerId>, Without<Downed>, Without<Eliminated>)>,
+    mut pool: Single<&mut Lives, With<TeamPool>>,
+    time: Res<Time>,
+    rules: Res<GameRules>,
+) {
+    for (ship, transform, mut downed) in &mut downed {
+        let position = transform.translation.xy();
+        let rescued = active
+            .iter()
+            .any(|it| it.translation.xy().distance(position) <= REVIVE_RADIUS);
+        if !rescued {
+            downed.set_if_neq(Downed::default());
+            continue;
+        }
+        downed.revive_progress += time.delta_secs() / REVIVE_TIME.as_secs_f32();
+        if downed.revive_progress >= 1.0 {
+            revive(&mut cmd, ship, &mut pool, &rules);
+        }
+    }
+}
+
+fn revive_on_wave(
+    mut cmd: Commands,
+    mut last_wave: Local<u32>,
+    clock: Res<MatchClock>,
+    downed: Query<Entity, With<Downed>>,
+    mut pool: Single<&mut Lives, With<TeamPool>>,
+    rules: Res<GameRules>,
+) {
+    let wave = clock.wave();
+    // A new match starts counting from the first wave again
+    if wave > *last_wave {
+        for ship in &downed {
+            if !revive(&mut cmd, ship, &mut pool, &rules) {
+                break;
+            }
+        }
+    }
+    *last_wave = wave;
+}
+
+/// Keeps the survival time of ships still in play up to date.
+fn track_survival(
+    mut players: Query<&mut PlayerStats, (With<PlayerId>, Without<Eliminated>)>,
+    clock: Res<MatchClock>,
+) {
+    let secs = clock.elapsed.as_secs() as u32;
+    for mut stats in &mut players {
+        if stats.survived_secs != secs {
+            stats.survived_secs = secs;
+        }
+    }
+}
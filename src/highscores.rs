@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::game_mode::GameMode;
+use crate::shared::MatchResults;
+use crate::spectator::LocalPlayer;
+
+pub struct HighScorePlugin;
+
+/// High scores are kept next to the executable's working directory.
+const HIGH_SCORES_FILE: &str = "highscores.txt";
+const HIGH_SCORES_PER_MODE: usize = 10;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load(HIGH_SCORES_FILE))
+            .add_systems(
+                Update,
+                record_high_scores.run_if(resource_exists_and_changed::<MatchResults>),
+            );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HighScore {
+    pub mode: GameMode,
+    /// Score or survival time, see [`GameMode::result`].
+    pub value: u32,
+    pub name: String,
+}
+
+impl HighScore {
+    fn encode(&self) -> String {
+        format!(
+            "{}\t{}\t{}",
+            self.mode.key(),
+            self.value,
+            self.name.replace(['\t', '\n'], " ")
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        Some(Self {
+            mode: GameMode::from_key(fields.next()?)?,
+            value: fields.next()?.parse().ok()?,
+            name: fields.next()?.to_string(),
+        })
+    }
+}
+
+/// Best results of this machine, a separate table per [`GameMode`].
+#[derive(Resource, Default)]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Missing or unreadable lines are skipped, so a broken file only loses those entries.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let entries = std::fs::read_to_string(path)
+            .map(|data| data.lines().filter_map(HighScore::decode).collect())
+            .unwrap_or_default();
+        Self { entries }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let data = self
+            .entries
+            .iter()
+            .map(|it| it.encode() + "\n")
+            .collect::<String>();
+        std::fs::write(path, data)
+    }
+
+    pub fn record(&mut self, entry: HighScore) {
+        self.entries.push(entry);
+        self.entries.sort_by(|a, b| b.value.cmp(&a.value));
+        let mut kept = Vec::new();
+        self.entries.retain(|it| {
+            let count = kept.iter().filter(|mode| **mode == it.mode).count();
+            kept.push(it.mode);
+            count < HIGH_SCORES_PER_MODE
+        });
+    }
+
+    pub fn top(&self, mode: GameMode) -> impl Iterator<Item = &HighScore> {
+        self.entries.iter().filter(move |it| it.mode == mode)
+    }
+}
+
+fn record_high_scores(
+    mut high_scores: ResMut<HighScores>,
+    results: Res<MatchResults>,
+    mode: Res<GameMode>,
+    local: Option<Res<LocalPlayer>>,
+) {
+    // Remote players record their own scores and bots are not listed
+    let Some(local) = local else {
+        return;
+    };
+    for player in results.players.iter().filter(|it| it.id == local.0) {
+        high_scores.record(HighScore {
+            mode: *mode,
+            value: mode.result(player),
+            name: player.name.clone(),
+        });
+    }
+    high_scores
+        .save(HIGH_SCORES_FILE)
+        .unwrap_or_else(|e| warn!("Failed to save high scores: {}", e));
+}
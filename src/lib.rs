@@ -0,0 +1,638 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use asteroid::AsteroidPlugin;
+use bevy::{prelude::*, render::camera::ScalingMode};
+use bevy_spatial::SpatialAccess;
+use bevy_spatial::kdtree::KDTree2;
+use bot::BotPlugin;
+use chat::{ChatLog, ChatPlugin, SendChat};
+use daily::DailyPlugin;
+use discovery::DiscoveryPlugin;
+use game_mode::{GameMode, GameModePlugin, MatchClock, MatchSettings, TeamPool};
+use highscores::HighScorePlugin;
+use leafwing_input_manager::prelude::*;
+use network_debug::{NetworkDebugPlugin, NetworkSettings};
+use particles::ParticlePlugin;
+use player::{Downed, Eliminated, PlayerId, PlayerName, PlayerPlugin, PlayerStats};
+use rules::RulesPlugin;
+use serde::Serialize;
+use shared::{MatchResults, PlayerResult};
+use simulation::{Headless, SimulationPlugin};
+use spectator::SpectatorPlugin;
+use strum::EnumIter;
+use ui::UiPlugin;
+
+pub mod asteroid;
+pub mod auth;
+pub mod bot;
+pub mod chat;
+pub mod client;
+pub mod daily;
+pub mod discovery;
+pub mod game_mode;
+pub mod highscores;
+pub mod network_debug;
+pub mod particles;
+pub mod player;
+pub mod rules;
+pub mod schema;
+pub mod server;
+pub mod shared;
+pub mod simulation;
+pub mod spectator;
+pub mod ui;
+pub mod weapon;
+
+pub type RngType = bevy_prng::ChaCha8Rng;
+pub const SERVER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5000);
+
+#[derive(Resource)]
+pub struct ServerAddress {
+    pub ip: String,
+    pub port: u16,
+}
+
+#[derive(Resource)]
+pub struct Nickname(pub String);
+
+impl Default for Nickname {
+    fn default() -> Self {
+        Self("Player".to_string())
+    }
+}
+
+impl Default for ServerAddress {
+    fn default() -> Self {
+        Self {
+            ip: Ipv4Addr::LOCALHOST.to_string(),
+            port: 5000,
+        }
+    }
+}
+
+/// The whole game. Parts of it can be left out to embed the game in other apps.
+pub struct GamePlugin {
+    client: bool,
+    server: bool,
+    headless: bool,
+    ui: bool,
+}
+
+impl Default for GamePlugin {
+    fn default() -> Self {
+        Self {
+            client: cfg!(feature = "client"),
+            server: cfg!(feature = "server"),
+            headless: false,
+            ui: true,
+        }
+    }
+}
+
+impl GamePlugin {
+    /// Only the simulation and bots, without rendering, networking or menus.
+    /// Runs under `MinimalPlugins` with `TransformPlugin` and `StatesPlugin`.
+    /// With [`GamePlugin::with_server`] it hosts games without a ship of its own,
+    /// which also needs the `AssetPlugin` to load the game rules.
+    pub fn headless() -> Self {
+        Self {
+            client: false,
+            server: false,
+            headless: true,
+            ui: false,
+        }
+    }
+
+    /// Whether this app can join games hosted elsewhere.
+    /// Unless headless, at least one of client and server is required.
+    pub fn with_client(mut self, client: bool) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Whether this app can host games.
+    pub fn with_server(mut self, server: bool) -> Self {
+        self.server = server;
+        self
+    }
+
+    /// Whether the menus, HUD and egui windows are shown.
+    pub fn with_ui(mut self, ui: bool) -> Self {
+        self.ui = ui;
+        self
+    }
+}
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((SimulationPlugin, GameModePlugin, BotPlugin));
+        if self.headless {
+            app.insert_resource(Headless);
+            if self.server {
+                app.add_plugins((RulesPlugin, server::ServerPlugin))
+                    .init_resource::<ServerAddress>()
+                    .init_resource::<Nickname>()
+                    .init_resource::<auth::SecuritySettings>()
+                    .init_resource::<NetworkSettings>()
+                    .init_resource::<ChatLog>()
+                    .add_event::<SendChat>()
+                    .add_plugins(crate::shared::SharedPlugin);
+            }
+            return;
+        }
+        assert!(
+            self.client || self.server,
+            "GamePlugin needs a client or a server outside of headless mode"
+        );
+
+        app.add_plugins(InputManagerPlugin::<player::PlayerAction>::default())
+            .add_plugins((
+                PlayerPlugin,
+                ParticlePlugin,
+                AsteroidPlugin,
+                ChatPlugin,
+                DiscoveryPlugin,
+                NetworkDebugPlugin,
+                SpectatorPlugin,
+                HighScorePlugin,
+                DailyPlugin,
+                RulesPlugin,
+            ))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (handle_restart).run_if(in_state(GameState::GameOver)),
+            )
+            .add_systems(OnEnter(GameState::Playing), cleanup::<CleanupOnGameStart>)
+            .add_systems(OnEnter(GameState::MainMenu), (cleanup::<CleanupOnRestart>,))
+            .add_systems(OnEnter(GameState::Lobby), (cleanup::<CleanupOnRestart>,))
+            .init_resource::<ServerAddress>()
+            .init_resource::<Nickname>()
+            .init_resource::<auth::SecuritySettings>()
+            .init_resource::<Language>();
+
+        if self.ui {
+            app.add_plugins(UiPlugin);
+        }
+        if self.client {
+            app.add_plugins((client::ClientPlugin,));
+        }
+        if self.server {
+            app.add_plugins((server::ServerPlugin,));
+        }
+
+        app.add_plugins(crate::shared::SharedPlugin); // Order of plugin initialization matters
+    }
+}
+
+#[derive(Event, Default)]
+pub struct HostGame {
+    /// Played alone on this instance, not announced on the LAN and closed to clients.
+    pub local: bool,
+}
+
+#[derive(Event)]
+pub struct JoinGame;
+
+pub const WINDOW_WIDTH: f32 = 1920.0;
+pub const WINDOW_HEIGHT: f32 = 1080.0;
+
+#[derive(States, Clone, Eq, PartialEq, Debug, Hash, Default)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    Lobby,
+    Playing,
+    GameOver,
+}
+
+rust_i18n::i18n!("locales", fallback = "en");
+
+#[derive(PartialEq, Default, Resource, Copy, Clone, EnumIter)]
+pub enum Language {
+    #[default]
+    English,
+    Polish,
+    French,
+}
+
+impl Language {
+    pub fn locale(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Polish => "pl",
+            Language::French => "fr",
+        }
+    }
+}
+
+fn handle_restart(key: Res<ButtonInput<KeyCode>>, mut state: ResMut<NextState<GameState>>) {
+    if key.just_pressed(KeyCode::KeyR) {
+        state.set(GameState::MainMenu);
+    }
+}
+
+#[derive(Component)]
+pub struct CleanupOnGameOver;
+
+#[derive(Component)]
+pub struct CleanupOnRestart;
+
+#[derive(Component)]
+pub struct CleanupOnGameStart;
+
+fn cleanup<T: Component>(mut cmd: Commands, e: Query<(Entity, &T)>) {
+    e.iter().for_each(|(e, _)| {
+        cmd.entity(e).despawn_recursive();
+    });
+}
+
+#[derive(Component, Default)]
+pub struct SpatialMarker;
+
+/// Kinds of colliders. A collider sits on one layer and masks the layers it collides with.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct CollisionLayers(u8);
+
+impl CollisionLayers {
+    pub const NONE: Self = Self(0);
+    pub const SHIP: Self = Self(1);
+    pub const ASTEROID: Self = Self(1 << 1);
+    pub const PROJECTILE: Self = Self(1 << 2);
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for CollisionLayers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Component)]
+#[require(SpatialMarker)]
+pub struct CircleCollider {
+    pub radius: f32,
+    pub layer: CollisionLayers,
+    pub mask: CollisionLayers,
+}
+
+impl CircleCollider {
+    pub fn new(radius: f32, layer: CollisionLayers, mask: CollisionLayers) -> Self {
+        Self {
+            radius,
+            layer,
+            mask,
+        }
+    }
+
+    pub fn ship(radius: f32) -> Self {
+        use CollisionLayers as L;
+        Self::new(radius, L::SHIP, L::ASTEROID | L::PROJECTILE)
+    }
+
+    pub fn asteroid(radius: f32) -> Self {
+        use CollisionLayers as L;
+        Self::new(radius, L::ASTEROID, L::SHIP | L::PROJECTILE)
+    }
+
+    pub fn projectile(radius: f32) -> Self {
+        use CollisionLayers as L;
+        Self::new(radius, L::PROJECTILE, L::SHIP | L::ASTEROID)
+    }
+
+    /// Both colliders have to mask each other's layer.
+    pub fn collides_with(&self, other: &CircleCollider) -> bool {
+        self.mask.intersects(other.layer) && other.mask.intersects(self.layer)
+    }
+
+    /// Mass of a body filling the circle, for bounces.
+    pub fn mass(&self) -> f32 {
+        self.radius.powi(2)
+    }
+}
+
+pub type NNTree = KDTree2<SpatialMarker>;
+
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct ShipHitAsteroid {
+    pub ship: Entity,
+    pub asteroid: Entity,
+}
+
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct ProjectileHitShip {
+    pub projectile: Entity,
+    pub ship: Entity,
+}
+
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct ProjectileHitAsteroid {
+    pub projectile: Entity,
+    pub asteroid: Entity,
+}
+
+/// Fast collider, tested along its whole path since the previous collision check so that it
+/// cannot tunnel through anything at low frame rates.
+#[derive(Component)]
+pub struct Swept(pub Vec2);
+
+fn segment_distance(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let path = end - start;
+    if path == Vec2::ZERO {
+        return point.distance(start);
+    }
+    let along = ((point - start).dot(path) / path.length_squared()).clamp(0.0, 1.0);
+    point.distance(start + path * along)
+}
+
+/// Largest radius of any collider seen so far, how far a swept collider looks around its path.
+#[derive(Resource, Default)]
+pub struct ColliderReach(f32);
+
+fn update_collider_reach(
+    colliders: Query<&CircleCollider, Changed<CircleCollider>>,
+    mut reach: ResMut<ColliderReach>,
+) {
+    // Never shrinks, a search wider than needed finds the same pairs
+    let largest = colliders.iter().map(|it| it.radius).fold(reach.0, f32::max);
+    if largest > reach.0 {
+        reach.0 = largest;
+    }
+}
+
+/// Finds overlapping colliders and reports every pair once, ordered by layer and then entity.
+fn check_collisions(
+    colliders: Query<(Entity, &Transform, &CircleCollider, Option<&Swept>)>,
+    tree: Res<NNTree>,
+    reach: Res<ColliderReach>,
+    mut ship_asteroid: EventWriter<ShipHitAsteroid>,
+    mut projectile_ship: EventWriter<ProjectileHitShip>,
+    mut projectile_asteroid: EventWriter<ProjectileHitAsteroid>,
+) {
+    let reach = reach.0;
+    let mut pairs = Vec::new();
+    colliders.iter().for_each(|(e, transform, col, swept)| {
+        let position = transform.translation.xy();
+        let (from, range) = match swept {
+            Some(Swept(from)) => (*from, from.distance(position) / 2.0 + col.radius + reach),
+            None => (position, col.radius),
+        };
+        tree.within_distance((from + position) / 2.0, range)
+            .iter()
+            .for_each(|(pos, entity)| {
+                let Some(other) = entity.filter(|it| *it != e) else {
+                    return;
+                };
+                let Ok((_, _, other_col, _)) = colliders.get(other) else {
+                    return;
+                };
+                if !col.collides_with(other_col) {
+                    return;
+                }
+                if swept.is_some()
+                    && segment_distance(*pos, from, position) > col.radius + other_col.radius
+                {
+                    return;
+                }
+                let (a, b) = ((col.layer, e), (other_col.layer, other));
+                pairs.push(if a <= b { (a, b) } else { (b, a) });
+            });
+    });
+    pairs.sort();
+    pairs.dedup();
+    for ((a_layer, a), (b_layer, b)) in pairs {
+        match (a_layer, b_layer) {
+            (CollisionLayers::SHIP, CollisionLayers::ASTEROID) => {
+                ship_asteroid.send(ShipHitAsteroid {
+                    ship: a,
+                    asteroid: b,
+                });
+            }
+            (CollisionLayers::SHIP, CollisionLayers::PROJECTILE) => {
+                projectile_ship.send(ProjectileHitShip {
+                    projectile: b,
+                    ship: a,
+                });
+            }
+            (CollisionLayers::ASTEROID, CollisionLayers::PROJECTILE) => {
+                projectile_asteroid.send(ProjectileHitAsteroid {
+                    projectile: b,
+                    asteroid: a,
+                });
+            }
+            _ => (),
+        }
+    }
+}
+
+fn setup(mut cmd: Commands) {
+    cmd.spawn((
+        Camera2d,
+        Projection::from(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: WINDOW_WIDTH,
+                height: WINDOW_HEIGHT,
+            },
+            ..OrthographicProjection::default_2d()
+        }),
+        Transform::from_xyz(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0, 0.0),
+    ));
+}
+
+#[derive(Component)]
+pub struct WrapTimeout(pub u8);
+
+#[derive(Component, PartialEq, Clone, Debug, serde::Deserialize, Serialize)]
+pub struct Lives(pub i8);
+
+/// Ends the match once the win or loss condition of the current [`GameMode`] is met.
+fn check_for_gameover(
+    mut cmd: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    players: Query<(
+        &PlayerId,
+        &PlayerName,
+        &Score,
+        &Lives,
+        &PlayerStats,
+        Has<Downed>,
+        Has<Eliminated>,
+    )>,
+    pool: Query<&Lives, With<TeamPool>>,
+    mode: Res<GameMode>,
+    settings: Res<MatchSettings>,
+    clock: Res<MatchClock>,
+) {
+    if players.is_empty() {
+        return;
+    }
+    if mode.is_over(
+        &settings,
+        &clock,
+        pool.get_single().ok(),
+        players
+            .iter()
+            .map(|(_, _, _, lives, stats, downed, eliminated)| (lives, stats, downed, eliminated)),
+    ) {
+        cmd.insert_resource(MatchResults::ranked(
+            players
+                .iter()
+                .map(|(id, name, score, _, stats, _, _)| PlayerResult {
+                    id: id.0,
+                    name: name.0.clone(),
+                    score: score.0,
+                    stats: stats.clone(),
+                })
+                .collect(),
+            *mode,
+        ));
+        state.set(GameState::GameOver);
+    }
+}
+
+#[derive(Component, Default, PartialEq, Clone, Debug, serde::Deserialize, Serialize)]
+pub struct Score(pub u32);
+
+/// Starts the next sweep of every [`Swept`] collider where this one ended.
+fn update_sweeps(mut swept: Query<(&mut Swept, &Transform)>) {
+    for (mut swept, transform) in &mut swept {
+        swept.0 = transform.translation.xy();
+    }
+}
+
+/// Triggered on a player entity to award points.
+#[derive(Event)]
+pub struct OnScoreUpdate(pub u32);
+
+fn wrap_around(
+    mut e: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&mut WrapTimeout>,
+            Option<&mut Swept>,
+        ),
+        With<Velocity>,
+    >,
+    mut cmd: Commands,
+) {
+    e.iter_mut().for_each(|(e, mut it, timeout, swept)| {
+        let before = it.translation.xy();
+        let mut wrapped = false;
+        if it.translation.x < 0.0 {
+            it.translation.x = 1920.0;
+            wrapped = true;
+        }
+        if it.translation.y < 0.0 {
+            it.translation.y = 1080.0;
+            wrapped = true;
+        }
+        if it.translation.y > 1080.0 {
+            it.translation.y = 0.0;
+            wrapped = true;
+        }
+        if it.translation.x > 1920.0 {
+            it.translation.x = 0.0;
+            wrapped = true;
+        }
+        // Keep the sweep on this side of the screen instead of across all of it
+        if let Some(mut swept) = swept.filter(|_| wrapped) {
+            swept.0 += it.translation.xy() - before;
+        }
+        if let Some(mut timeout) = timeout {
+            if !wrapped {
+                return;
+            }
+            if wrapped && timeout.0 == 0 {
+                cmd.entity(e).despawn();
+                return;
+            }
+            timeout.0 -= 1;
+        }
+    });
+}
+
+#[derive(Component, PartialEq, serde::Deserialize, Serialize)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Velocity {
+    pub fn max(&mut self, val: f32) {
+        if (self.x.powi(2) + self.y.powi(2)).sqrt() > val {
+            let angle = self.y.atan2(self.x);
+            self.x = angle.cos() * val;
+            self.y = angle.sin() * val;
+        }
+    }
+    pub fn update(&mut self, translation: Vec2) {
+        self.x += translation.x;
+        self.y += translation.y;
+    }
+}
+
+/// Turn rate of a ship in radians per second, counterclockwise.
+#[derive(Component, PartialEq, Default, Debug)]
+pub struct AngularVelocity(pub f32);
+
+impl AngularVelocity {
+    /// Turns towards `target`, taking `inertia` seconds to get there from standstill.
+    pub fn steer(&mut self, target: f32, inertia: f32, delta: f32) {
+        if inertia <= 0.0 {
+            self.0 = target;
+            return;
+        }
+        let step = target.abs() / inertia * delta;
+        self.0 += (target - self.0).clamp(-step, step);
+    }
+}
+
+/// Pixels per second moved for each unit of [`Velocity`].
+pub const VELOCITY_SCALE: f32 = 100.0;
+
+/// Runs on the fixed tick, so movement does not depend on the frame rate.
+fn apply_velocity(mut e: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
+    e.iter_mut().for_each(|mut it| {
+        it.0.translation.x += it.1.x * time.delta_secs() * VELOCITY_SCALE;
+        it.0.translation.y += it.1.y * time.delta_secs() * VELOCITY_SCALE;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::prelude::*;
+
+    use crate::{Velocity, apply_velocity};
+
+    #[test]
+    fn velocity_applied() {
+        let mut world = World::default();
+
+        world.init_resource::<Time>();
+        let mut time = world.get_resource_mut::<Time>().unwrap();
+        time.advance_by(Duration::from_secs(1));
+
+        let system = world.register_system(apply_velocity);
+
+        let obj = world
+            .spawn((Transform::default(), Velocity { x: 1.0, y: 1.0 }))
+            .id();
+
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.x, 0.0);
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.y, 0.0);
+        let _ = world.run_system(system);
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.x, 100.0);
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.y, 100.0);
+        let _ = world.run_system(system);
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.x, 200.0);
+        assert_eq!(world.get::<Transform>(obj).unwrap().translation.y, 200.0);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use bevy::diagnostic::DiagnosticsStore;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use egui::Align2;
+use lightyear::channel::builder::{EntityActionsChannel, EntityUpdatesChannel};
+use lightyear::prelude::*;
+use lightyear::shared::events::components::MessageEvent;
+use rust_i18n::t;
+use serde::Serialize;
+
+use crate::shared::{ProbeChannel, ProbeMessage, SERVER_REPLICATION_INTERVAL};
+
+/// Time between two probes sent by the server.
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+/// Number of latest probes packet loss is measured over.
+const PROBE_WINDOW: usize = 100;
+/// Time over which the received bytes are averaged into a rate.
+const TRAFFIC_WINDOW: Duration = Duration::from_secs(2);
+
+pub struct NetworkDebugPlugin;
+
+impl Plugin for NetworkDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkSettings>()
+            .init_resource::<ShowNetworkDiagnostics>()
+            .init_resource::<NetworkTraffic>()
+            .add_systems(
+                Update,
+                (
+                    toggle_diagnostics,
+                    diagnostics_overlay.run_if(resource_equals(ShowNetworkDiagnostics(true))),
+                ),
+            );
+    }
+}
+
+/// Simulated network conditions and replication tuning, applied when hosting or joining.
+///
+/// Can be set from the command line with `--latency <ms>`, `--jitter <ms>`,
+/// `--loss <0.0-1.0>` and `--replication-interval <ms>`.
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct NetworkSettings {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub loss: f32,
+    pub replication_interval_ms: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            latency_ms: 0,
+            jitter_ms: 0,
+            loss: 0.0,
+            replication_interval_ms: SERVER_REPLICATION_INTERVAL.as_millis() as u64,
+        }
+    }
+}
+
+impl NetworkSettings {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut settings = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "--soak" {
+                // Handled by the bot soak test
+                args.next();
+                continue;
+            }
+            if arg == "--headless" {
+                // Handled by the binary
+                continue;
+            }
+            if !matches!(
+                arg.as_str(),
+                "--latency" | "--jitter" | "--loss" | "--replication-interval"
+            ) {
+                warn!("Unknown argument {}", arg);
+                continue;
+            }
+            let value = args.next().unwrap_or_default();
+            let parsed = match arg.as_str() {
+                "--latency" => value.parse().map(|it| settings.latency_ms = it).is_ok(),
+                "--jitter" => value.parse().map(|it| settings.jitter_ms = it).is_ok(),
+                "--loss" => value
+                    .parse::<f32>()
+                    .map(|it| settings.loss = it.clamp(0.0, 1.0))
+                    .is_ok(),
+                _ => value
+                    .parse()
+                    .map(|it| settings.replication_interval_ms = it)
+                    .is_ok(),
+            };
+            if !parsed {
+                warn!("Invalid value '{}' for {}", value, arg);
+            }
+        }
+        settings
+    }
+
+    pub fn conditioner(&self) -> Option<LinkConditionerConfig> {
+        if self.latency_ms == 0 && self.jitter_ms == 0 && self.loss == 0.0 {
+            return None;
+        }
+        Some(LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(self.latency_ms),
+            incoming_jitter: Duration::from_millis(self.jitter_ms),
+            incoming_loss: self.loss,
+        })
+    }
+
+    pub fn replication_interval(&self) -> Duration {
+        Duration::from_millis(self.replication_interval_ms)
+    }
+
+    /// Debug menu controls, only effective before hosting or joining.
+    pub fn edit(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.latency_ms, 0..=500).text(t!("netsim.latency")));
+        ui.add(egui::Slider::new(&mut self.jitter_ms, 0..=200).text(t!("netsim.jitter")));
+        ui.add(egui::Slider::new(&mut self.loss, 0.0..=0.5).text(t!("netsim.loss")));
+        ui.add(
+            egui::Slider::new(&mut self.replication_interval_ms, 0..=500)
+                .text(t!("netsim.replication_interval")),
+        );
+    }
+}
+
+#[derive(Default, Resource, PartialEq)]
+pub struct ShowNetworkDiagnostics(pub bool);
+
+/// Traffic this client received, lightyear only counts the bytes of whole packets.
+#[derive(Resource, Default, Debug)]
+pub struct NetworkTraffic {
+    /// Arrival time, channel and encoded size of everything received within the window.
+    received: VecDeque<(Duration, &'static str, usize)>,
+    /// Numbers of the latest probes received.
+    probes: VecDeque<u32>,
+}
+
+impl NetworkTraffic {
+    fn record(&mut self, now: Duration, channel: &'static str, bytes: usize) {
+        while self
+            .received
+            .front()
+            .is_some_and(|(at, ..)| now.saturating_sub(*at) > TRAFFIC_WINDOW)
+        {
+            self.received.pop_front();
+        }
+        self.received.push_back((now, channel, bytes));
+    }
+
+    /// Bytes per second received on each channel over the window.
+    pub fn channel_rates(&self, now: Duration) -> BTreeMap<&'static str, f32> {
+        let mut rates = BTreeMap::new();
+        for (at, channel, bytes) in &self.received {
+            let rate = rates.entry(*channel).or_default();
+            if now.saturating_sub(*at) <= TRAFFIC_WINDOW {
+                *rate += *bytes as f32 / TRAFFIC_WINDOW.as_secs_f32();
+            }
+        }
+        rates
+    }
+
+    /// Share of the latest probes that never arrived.
+    pub fn packet_loss(&self) -> Option<f32> {
+        let first = self.probes.iter().min()?;
+        let last = self.probes.iter().max()?;
+        let expected = (last - first + 1) as f32;
+        Some(1.0 - self.probes.len() as f32 / expected)
+    }
+}
+
+/// Size of `value` as lightyear encodes it, without the packet and channel headers.
+fn wire_size<T: Serialize>(value: &T) -> usize {
+    bincode::serde::encode_to_vec(value, bincode::config::standard()).map_or(0, |it| it.len())
+}
+
+/// Adds the messages of type `M` received on channel `C` to the [`NetworkTraffic`].
+pub fn count_messages<C: Channel, M: Message + Serialize>(
+    mut events: EventReader<MessageEvent<M>>,
+    mut traffic: ResMut<NetworkTraffic>,
+    time: Res<Time<Real>>,
+) {
+    for event in events.read() {
+        traffic.record(time.elapsed(), C::name(), wire_size(event.message()));
+    }
+}
+
+/// Adds the replicated inserts and updates of `C` to the [`NetworkTraffic`], under the
+/// channels lightyear replicates them on.
+pub fn count_replication<C: Component + Serialize>(
+    mut inserts: EventReader<client::ComponentInsertEvent<C>>,
+    mut updates: EventReader<client::ComponentUpdateEvent<C>>,
+    components: Query<&C>,
+    mut traffic: ResMut<NetworkTraffic>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed();
+    for event in inserts.read() {
+        if let Ok(component) = components.get(event.entity()) {
+            traffic.record(now, EntityActionsChannel::name(), wire_size(component));
+        }
+    }
+    for event in updates.read() {
+        if let Ok(component) = components.get(event.entity()) {
+            traffic.record(now, EntityUpdatesChannel::name(), wire_size(component));
+        }
+    }
+}
+
+pub fn receive_probes(
+    mut events: EventReader<MessageEvent<ProbeMessage>>,
+    mut traffic: ResMut<NetworkTraffic>,
+) {
+    for event in events.read() {
+        let probe = event.message().0;
+        // A restarted server counts from zero again
+        if traffic
+            .probes
+            .back()
+            .is_some_and(|last| probe + PROBE_WINDOW as u32 <= *last)
+        {
+            traffic.probes.clear();
+        }
+        traffic.probes.push_back(probe);
+        if traffic.probes.len() > PROBE_WINDOW {
+            traffic.probes.pop_front();
+        }
+    }
+}
+
+pub fn send_probes(
+    mut server: ResMut<server::ConnectionManager>,
+    time: Res<Time<Real>>,
+    mut last_sent: Local<Duration>,
+    mut sequence: Local<u32>,
+) {
+    if time.elapsed() - *last_sent < PROBE_INTERVAL {
+        return;
+    }
+    *last_sent = time.elapsed();
+    server
+        .send_message_to_target::<ProbeChannel, ProbeMessage>(
+            &ProbeMessage(*sequence),
+            NetworkTarget::All,
+        )
+        .unwrap_or_else(|e| {
+            error!("Failed to send probe: {}", e);
+        });
+    *sequence = sequence.wrapping_add(1);
+}
+
+fn toggle_diagnostics(key: Res<ButtonInput<KeyCode>>, mut show: ResMut<ShowNetworkDiagnostics>) {
+    if key.just_pressed(KeyCode::F3) {
+        show.0 = !show.0;
+    }
+}
+
+fn diagnostics_overlay(
+    mut ctx: EguiContexts,
+    settings: Res<NetworkSettings>,
+    tick_manager: Res<TickManager>,
+    connection: Option<Res<client::ConnectionManager>>,
+    traffic: Res<NetworkTraffic>,
+    time: Res<Time<Real>>,
+    diagnostics: Res<DiagnosticsStore>,
+    replicated: Query<(), With<Replicated>>,
+    replicating: Query<(), With<Replicating>>,
+    server_state: Option<Res<State<server::NetworkingState>>>,
+) {
+    let is_host =
+        server_state.is_some_and(|state| *state.get() == server::NetworkingState::Started);
+    // Only clients measure the link to a server
+    let connection = connection.filter(|_| !is_host);
+    let Some(egui_ctx) = ctx.try_ctx_mut() else {
+        return;
+    };
+    let rect = egui_ctx.input(|i: &egui::InputState| i.screen_rect());
+    egui::Window::new(t!("netstats"))
+        .pivot(Align2::RIGHT_TOP)
+        .current_pos(egui::Pos2::new(rect.max.x - 10.0, 10.0))
+        .show(egui_ctx, |ui| {
+            egui::Grid::new("netstats").show(ui, |ui| {
+                ui.label(t!("netstats.tick"));
+                ui.label(tick_manager.tick().0.to_string());
+                ui.end_row();
+                if let Some(connection) = &connection {
+                    ui.label(t!("netstats.rtt"));
+                    ui.label(format!(
+                        "{:.1} ms",
+                        connection.ping_manager.rtt().as_secs_f64() * 1000.0
+                    ));
+                    ui.end_row();
+                    ui.label(t!("netstats.jitter"));
+                    ui.label(format!(
+                        "{:.1} ms",
+                        connection.ping_manager.jitter().as_secs_f64() * 1000.0
+                    ));
+                    ui.end_row();
+                    ui.label(t!("netstats.loss"));
+                    ui.label(
+                        traffic
+                            .packet_loss()
+                            .map_or("-".to_string(), |loss| format!("{:.1}%", loss * 100.0)),
+                    );
+                    ui.end_row();
+                    // Estimated from the payloads re-encoded, lightyear only counts whole packets
+                    for (channel, rate) in traffic.channel_rates(time.elapsed()) {
+                        ui.label(t!("netstats.channel", channel = channel));
+                        ui.label(format!("~{:.2} KB/s", rate / 1000.0));
+                        ui.end_row();
+                    }
+                }
+                ui.label(t!("netstats.entities"));
+                ui.label(if is_host {
+                    replicating.iter().count().to_string()
+                } else {
+                    replicated.iter().count().to_string()
+                });
+                ui.end_row();
+                ui.label(t!("netstats.replication_interval"));
+                ui.label(format!("{} ms", settings.replication_interval_ms));
+                ui.end_row();
+                ui.label(t!("netstats.simulated"));
+                ui.label(format!(
+                    "{} ± {} ms, {:.0}%",
+                    settings.latency_ms,
+                    settings.jitter_ms,
+                    settings.loss * 100.0
+                ));
+                ui.end_row();
+            });
+            ui.separator();
+            egui::Grid::new("diagnostics").show(ui, |ui| {
+                for diagnostic in diagnostics.iter() {
+                    let Some(value) = diagnostic.smoothed() else {
+                        continue;
+                    };
+                    ui.label(diagnostic.path().as_str());
+                    ui.label(format!("{:.2} {}", value, diagnostic.suffix));
+                    ui.end_row();
+                }
+            });
+        });
+}
@@ -6,6 +6,8 @@ use bevy_hanabi::{
     SetPositionSphereModifier, SetVelocitySphereModifier, ShapeDimension, Spawner,
 };
 
+use crate::asteroid::AsteroidDestroyed;
+
 pub struct ParticlePlugin;
 
 impl Plugin for ParticlePlugin {
@@ -13,6 +15,7 @@ impl Plugin for ParticlePlugin {
         app.add_systems(Startup, setup)
             .add_systems(Update, cleanup_after_timeout)
             .init_resource::<CollisionEffect>()
+            .add_observer(explode_asteroid)
             .add_plugins(HanabiPlugin);
     }
 }
@@ -42,6 +45,21 @@ fn cleanup_after_timeout(
     });
 }
 
+fn explode_asteroid(
+    trigger: Trigger<AsteroidDestroyed>,
+    mut cmd: Commands,
+    effect: Res<CollisionEffect>,
+) {
+    cmd.spawn((
+        ParticleEffectBundle {
+            effect: ParticleEffect::new(effect.0.clone()),
+            transform: trigger.event().0,
+            ..default()
+        },
+        CleanupAfterTimeout::default(),
+    ));
+}
+
 fn setup(
     mut effect: ResMut<CollisionEffect>,
     mut effects: ResMut<Assets<EffectAsset>>,
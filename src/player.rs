@@ -1,23 +1,36 @@
+use std::collections::HashMap;
+
 use bevy::{prelude::*, time::Timer};
+use bevy_rand::global::GlobalEntropy;
+use bevy_spatial::SpatialAccess;
 use client::InputManager;
 use leafwing_input_manager::{
     Actionlike, InputManagerBundle,
     prelude::{ActionState, InputMap},
 };
 use lightyear::{client::input::native::InputSystemSet, prelude::*};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, IntoEnumIterator};
 
 use crate::{
-    ACC_SPEED, CircleCollider, CleanupOnGameOver, CollisionEvent, GameState, MAX_VELOCITY,
-    OnScoreUpdate, PROJECTILE_SPEED, ROTATION_SPEED, SHOOT_TIMEOUT, Velocity, WINDOW_HEIGHT,
-    WINDOW_WIDTH, WrapTimeout,
+    AngularVelocity, CircleCollider, CleanupOnGameOver, GameState, Lives, NNTree, Nickname,
+    OnScoreUpdate, ProjectileHitAsteroid, RngType, Score, ShipHitAsteroid, Velocity, WINDOW_HEIGHT,
+    WINDOW_WIDTH,
+    asteroid::{LargeAsteroid, destroy_asteroid},
+    chat::is_typing,
+    game_mode::{GameMode, MatchSettings, TeamPool},
+    rules::{AsteroidPhysics, GameRules},
+    simulation::{Fire, ResolvedHits, ShipAction, ShotCooldown, apply_ship_actions},
+    spectator::LocalPlayer,
+    weapon::{LASER_WIDTH, WeaponKind, Weapons},
 };
 
 pub struct PlayerPlugin;
 
 #[derive(Component)]
 pub struct Player {
+    /// Holds back shots the server would refuse anyway.
     projectile_spawn_delay: Timer,
 }
 
@@ -32,11 +45,59 @@ pub enum PlayerShadow {
 #[derive(Actionlike, Debug, Clone, Eq, PartialEq, Hash, Reflect, Serialize, Deserialize)]
 pub enum PlayerAction {
     Forward,
+    /// Retro thrust, braking or backing up depending on the physics profile.
+    Reverse,
     Shoot,
     Rotate(i8),
+    /// Cycles through the weapons the ship carries.
+    SwitchWeapon,
     None,
 }
 
+/// Everything a player does during one tick. Clients send one per tick, and lightyear
+/// repeats the last few in every packet, so a lost packet drops no input.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub struct ShipInput {
+    /// 1 fires the main thruster, -1 the retro thrusters.
+    pub thrust: i8,
+    /// Positive values turn clockwise, like [`PlayerAction::Rotate`].
+    pub rotate: i8,
+    pub shoot: bool,
+    /// Held down, ships switch once per press.
+    pub switch_weapon: bool,
+}
+
+impl ShipInput {
+    /// Whether an honest client could have sent this input.
+    pub fn is_valid(&self) -> bool {
+        (-1..=1).contains(&self.thrust) && (-1..=1).contains(&self.rotate)
+    }
+}
+
+/// Combines the actions held at once, opposite ones cancel out.
+impl FromIterator<PlayerAction> for ShipInput {
+    fn from_iter<I: IntoIterator<Item = PlayerAction>>(actions: I) -> Self {
+        let mut input = Self::default();
+        for action in actions {
+            match action {
+                PlayerAction::Forward => input.thrust = input.thrust.saturating_add(1),
+                PlayerAction::Reverse => input.thrust = input.thrust.saturating_sub(1),
+                PlayerAction::Rotate(sign) => input.rotate = input.rotate.saturating_add(sign),
+                PlayerAction::Shoot => input.shoot = true,
+                PlayerAction::SwitchWeapon => input.switch_weapon = true,
+                PlayerAction::None => (),
+            }
+        }
+        input
+    }
+}
+
+impl From<PlayerAction> for ShipInput {
+    fn from(action: PlayerAction) -> Self {
+        std::iter::once(action).collect()
+    }
+}
+
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup)
@@ -44,32 +105,99 @@ impl Plugin for PlayerPlugin {
                 OnEnter(GameState::Playing),
                 (game_setup, host_setup.run_if(is_server)).chain(),
             )
+            .add_systems(
+                FixedUpdate,
+                player_input.before(apply_ship_actions).run_if(
+                    in_state(GameState::Playing)
+                        .and(is_server)
+                        .and(not(is_typing)),
+                ),
+            )
             .add_systems(
                 FixedPreUpdate,
-                input_passthrough.in_set(InputSystemSet::BufferInputs),
+                input_passthrough
+                    .in_set(InputSystemSet::BufferInputs)
+                    .run_if(not(is_typing)),
             )
             .add_systems(
                 Update,
                 (
-                    player_input.run_if(is_server),
                     apply_shadow,
-                    shoot_projectile.run_if(is_server),
-                    resolve_bullet_collisions,
-                    resolve_player_collisions,
-                    clear_player_grace,
+                    shoot_projectile.run_if(is_server.and(not(is_typing))),
+                    hide_eliminated,
+                    attach_projectile_sprite,
                 )
                     .run_if(in_state(GameState::Playing)),
-            )
-            .add_observer(player_grace);
+            );
     }
 }
 
+/// Look of the projectiles of each weapon.
 #[derive(Resource)]
-pub struct ProjectileSprite(pub Handle<ColorMaterial>, pub Handle<Mesh>);
+pub struct ProjectileSprites(pub HashMap<WeaponKind, (Handle<ColorMaterial>, Handle<Mesh>)>);
 
-#[derive(Component, PartialEq, Serialize, Deserialize)]
+#[derive(Component, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PlayerId(pub u64);
 
+#[derive(Component, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerName(pub String);
+
+/// Per-match statistics shown on the results screen.
+#[derive(Component, PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub asteroids_destroyed: u32,
+    pub kills: u32,
+    /// Seconds the ship stayed in play, only tracked in survival.
+    pub survived_secs: u32,
+}
+
+impl PlayerStats {
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            return 0.0;
+        }
+        self.shots_hit as f32 / self.shots_fired as f32
+    }
+}
+
+/// Marks a player that has run out of lives.
+#[derive(Component, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Eliminated;
+
+/// Marks a co-op player that was hit and waits for a teammate to revive them.
+#[derive(Component, PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Downed {
+    /// Fraction of the revive done by teammates nearby, from 0 to 1.
+    pub revive_progress: f32,
+}
+
+/// Player that fired a projectile.
+#[derive(Component, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Owner(pub PlayerId);
+
+/// Components every player ship carries on the server.
+pub fn player_state(
+    id: u64,
+    name: String,
+    rules: &GameRules,
+    loadout: &[WeaponKind],
+) -> impl Bundle {
+    (
+        PlayerId(id),
+        PlayerName(name),
+        Score::default(),
+        Lives(rules.lives),
+        PlayerStats::default(),
+        AngularVelocity::default(),
+        CircleCollider::ship(rules.ship_radius),
+        ShotCooldown::new(rules),
+        Weapons::new(loadout),
+        CleanupOnGameOver,
+    )
+}
+
 #[derive(Component)]
 pub struct PlayerSpawner {
     mesh: Handle<Mesh>,
@@ -87,6 +215,11 @@ impl PlayerSpawner {
             MeshMaterial2d(self.material.clone()),
         )
     }
+
+    /// Ship shape drawn with another material, e.g. for replays.
+    pub fn player_ghost(&self, material: Handle<ColorMaterial>) -> impl Bundle {
+        (Mesh2d(self.mesh.clone()), MeshMaterial2d(material))
+    }
 }
 
 fn setup(
@@ -95,10 +228,40 @@ fn setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    cmd.insert_resource(ProjectileSprite(
-        materials.add(Color::linear_rgb(0.0, 256.0, 0.0)),
-        meshes.add(Circle::new(20.0)),
-    ));
+    let mut sprite = |color: Color, mesh: Mesh| (materials.add(color), meshes.add(mesh));
+    cmd.insert_resource(ProjectileSprites(HashMap::from([
+        (
+            WeaponKind::Standard,
+            sprite(Color::linear_rgb(0.0, 256.0, 0.0), Circle::new(20.0).into()),
+        ),
+        (
+            // Beams are stretched to their length
+            WeaponKind::Laser,
+            sprite(
+                Color::linear_rgb(0.0, 256.0, 256.0),
+                Rectangle::new(LASER_WIDTH, 1.0).into(),
+            ),
+        ),
+        (
+            WeaponKind::Mine,
+            sprite(
+                Color::linear_rgb(256.0, 256.0, 0.0),
+                RegularPolygon::new(15.0, 6).into(),
+            ),
+        ),
+        (
+            WeaponKind::Missile,
+            sprite(
+                Color::linear_rgb(256.0, 128.0, 0.0),
+                Triangle2d::new(
+                    Vec2::new(0.0, 15.0),
+                    Vec2::new(-8.0, -10.0),
+                    Vec2::new(8.0, -10.0),
+                )
+                .into(),
+            ),
+        ),
+    ])));
     let player_mesh = meshes.add(Triangle2d::new(
         Vec2::new(0.0, 50.0),
         Vec2::new(-50.0, -50.0),
@@ -107,18 +270,27 @@ fn setup(
     let mat = materials.add(Color::linear_rgb(256.0, 0.0, 0.0));
     cmd.spawn(PlayerSpawner::new(player_mesh.clone(), mat.clone()));
 }
-fn host_setup(mut cmd: Commands, spawner: Single<&PlayerSpawner>, e: Single<Entity, With<Player>>) {
-    cmd.entity(*e).insert(spawner.player_client());
+fn host_setup(
+    mut cmd: Commands,
+    spawner: Single<&PlayerSpawner>,
+    e: Single<Entity, With<Player>>,
+    nickname: Res<Nickname>,
+    rules: Res<GameRules>,
+    settings: Res<MatchSettings>,
+) {
+    cmd.entity(*e).insert((
+        spawner.player_client(),
+        player_state(0, nickname.0.clone(), &rules, &settings.loadout),
+    ));
 }
 
-fn game_setup(mut cmd: Commands, spawner: Single<&PlayerSpawner>) {
+fn game_setup(mut cmd: Commands, spawner: Single<&PlayerSpawner>, rules: Res<GameRules>) {
     cmd.spawn((
         //spawner.player_client(),
         Transform::from_xyz(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0, 0.0),
         Velocity { x: 0.0, y: 0.0 },
-        Player::default(),
+        Player::new(&rules),
         InputManagerBundle::<PlayerAction>::with_map(Player::default_input_map()),
-        CircleCollider::new(15.0),
         CleanupOnGameOver,
         PlayerId(0),
         server::Replicate::default(),
@@ -133,15 +305,13 @@ fn game_setup(mut cmd: Commands, spawner: Single<&PlayerSpawner>) {
     }
 }
 
-impl Default for Player {
-    fn default() -> Self {
+impl Player {
+    pub fn new(rules: &GameRules) -> Self {
         Self {
-            projectile_spawn_delay: Timer::from_seconds(SHOOT_TIMEOUT, TimerMode::Once),
+            projectile_spawn_delay: Timer::from_seconds(rules.shoot_timeout, TimerMode::Once),
         }
     }
-}
 
-impl Player {
     pub fn default_input_map() -> InputMap<PlayerAction> {
         let mut input_map = InputMap::default();
 
@@ -149,6 +319,9 @@ impl Player {
         input_map.insert(Forward, KeyCode::ArrowUp);
         input_map.insert(Forward, KeyCode::KeyW);
 
+        input_map.insert(Reverse, KeyCode::ArrowDown);
+        input_map.insert(Reverse, KeyCode::KeyS);
+
         input_map.insert(Rotate(-1), KeyCode::ArrowLeft);
         input_map.insert(Rotate(-1), KeyCode::KeyA);
 
@@ -157,100 +330,90 @@ impl Player {
 
         input_map.insert(Shoot, KeyCode::Space);
 
+        input_map.insert(SwitchWeapon, KeyCode::KeyQ);
+        input_map.insert(SwitchWeapon, KeyCode::ShiftRight);
+
         input_map
     }
 }
 
 fn input_passthrough(
     tick_manager: Res<TickManager>,
-    mut input_manager: ResMut<InputManager<PlayerAction>>,
+    mut input_manager: ResMut<InputManager<ShipInput>>,
     player: Option<Single<(&mut Player, &ActionState<PlayerAction>)>>,
+    ships: Query<(&PlayerId, &Weapons)>,
+    local: Option<Res<LocalPlayer>>,
+    rules: Res<GameRules>,
     time: Res<Time>,
 ) {
     if player.is_none() {
         return;
     }
     let (mut timer, player) = player.unwrap().into_inner();
-    let tick = tick_manager.tick();
-    let mut some = false;
     timer.projectile_spawn_delay.tick(time.delta());
-    for key in player.get_pressed() {
-        if key == PlayerAction::Shoot {
-            if timer.projectile_spawn_delay.finished() {
-                timer.projectile_spawn_delay.reset();
-                input_manager.add_input(key, tick);
-                some = true;
-            }
-            continue;
+    let mut input: ShipInput = player.get_pressed().into_iter().collect();
+    if input.shoot {
+        if timer.projectile_spawn_delay.finished() {
+            // The next shot waits out the cooldown of the weapon firing this one
+            let kind = ships
+                .iter()
+                .find(|(id, _)| local.as_ref().is_some_and(|local| local.0 == id.0))
+                .map(|(_, weapons)| weapons.current().kind)
+                .unwrap_or_default();
+            timer.projectile_spawn_delay =
+                Timer::from_seconds(kind.cooldown(&rules), TimerMode::Once);
+        } else {
+            input.shoot = false;
         }
-        input_manager.add_input(key, tick);
-        some = true;
-    }
-    if !some {
-        input_manager.add_input(PlayerAction::None, tick);
     }
+    input_manager.add_input(input, tick_manager.tick());
 }
 
+/// Steers the host's ship through the simulation like any other, shooting is left to
+/// [`shoot_projectile`].
 pub fn player_input(
-    player: Single<(&mut Velocity, &mut Transform, &ActionState<PlayerAction>), With<Player>>,
-    time: Res<Time>,
+    player: Single<(&PlayerId, &ActionState<PlayerAction>), With<Player>>,
+    mut actions: EventWriter<ShipAction>,
 ) {
-    let (mut velocity, mut transform, action_state) = player.into_inner();
-    let direction = transform.rotation * Vec3::Y;
-    let translation = direction * ACC_SPEED * time.delta().as_secs_f32();
-
-    if action_state.pressed(&PlayerAction::Forward) {
-        velocity.update(translation.xy());
-    }
-    velocity.max(MAX_VELOCITY);
-
-    if action_state.pressed(&PlayerAction::Rotate(-1)) {
-        transform.rotate_z(ROTATION_SPEED * time.delta_secs());
-    }
-    if action_state.pressed(&PlayerAction::Rotate(1)) {
-        transform.rotate_z(-ROTATION_SPEED * time.delta_secs());
-    }
+    let (id, action_state) = player.into_inner();
+    let input = action_state
+        .get_pressed()
+        .into_iter()
+        .filter(|it| *it != PlayerAction::Shoot)
+        .collect();
+    actions.send(ShipAction {
+        player: id.0,
+        input,
+    });
 }
 
 #[derive(Component, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct ScoreMarker;
 
+/// Fires the host's gun, the simulation decides whether it is ready.
 fn shoot_projectile(
-    player: Single<(
-        &Transform,
-        &Velocity,
-        &ActionState<PlayerAction>,
-        &mut Player,
-    )>,
+    player: Single<
+        (Entity, &ActionState<PlayerAction>),
+        (With<Player>, Without<Eliminated>, Without<Downed>),
+    >,
     mut cmd: Commands,
-    time: Res<Time>,
-    material: Option<Res<ProjectileSprite>>,
 ) {
-    if let Some(material) = material {
-        let (player, velocity, action_state, mut timer) = player.into_inner();
-        timer.projectile_spawn_delay.tick(time.delta());
+    let (e, action_state) = player.into_inner();
+    if action_state.just_pressed(&PlayerAction::Shoot) {
+        cmd.trigger_targets(Fire, e);
+    }
+}
 
-        if action_state.just_pressed(&PlayerAction::Shoot)
-            && timer.projectile_spawn_delay.finished()
-        {
-            let direction = player.rotation * Vec3::Y;
-            cmd.spawn((
-                Mesh2d(material.1.clone()),
-                Transform::from_translation(player.translation),
-                MeshMaterial2d(material.0.clone()),
-                Velocity {
-                    x: velocity.x + direction.x * PROJECTILE_SPEED,
-                    y: velocity.y + direction.y * PROJECTILE_SPEED,
-                },
-                WrapTimeout(1),
-                CircleCollider::new(10.0),
-                ScoreMarker,
-                CleanupOnGameOver,
-            ));
-            timer.projectile_spawn_delay.reset();
-        }
-    } else {
-        warn!("Projectile material not loaded");
+/// Projectiles fired on the host or replicated from the server get drawn.
+fn attach_projectile_sprite(
+    mut cmd: Commands,
+    projectiles: Query<(Entity, &WeaponKind), Added<ScoreMarker>>,
+    sprites: Res<ProjectileSprites>,
+) {
+    for (e, kind) in &projectiles {
+        let (material, mesh) = &sprites.0[kind];
+        cmd.entity(e)
+            .insert((Mesh2d(mesh.clone()), MeshMaterial2d(material.clone())));
     }
 }
 
@@ -281,8 +444,9 @@ pub fn apply_shadow(
 #[derive(Event)]
 pub struct OnPlayerDamage;
 
+/// Short invulnerability after taking damage or dropping into a match.
 #[derive(Component)]
-struct PlayerGrace {
+pub struct PlayerGrace {
     timer: Timer,
 }
 
@@ -294,25 +458,41 @@ impl Default for PlayerGrace {
     }
 }
 
-fn resolve_player_collisions(
-    mut e: EventReader<CollisionEvent>,
+/// Asteroids break on any ship they touch, hurting it unless it is in grace.
+pub fn resolve_player_collisions(
+    mut e: EventReader<ShipHitAsteroid>,
     mut cmd: Commands,
-    player: Query<Entity, (With<Player>, Without<PlayerGrace>)>,
-    bullets: Query<Entity, With<ScoreMarker>>,
+    player: Query<Entity, (With<PlayerId>, Without<PlayerGrace>, Without<Eliminated>)>,
+    asteroids: Query<(&Transform, Has<LargeAsteroid>)>,
+    rules: Res<GameRules>,
+    mut hits: ResMut<ResolvedHits>,
 ) {
+    // Bouncing asteroids only hurt ships ramming them, see `bounce_asteroids`
+    if rules.asteroid_physics == AsteroidPhysics::Elastic {
+        e.clear();
+        return;
+    }
     for ev in e.read() {
-        if (player.get(ev.0).is_ok() && bullets.get(ev.1).is_err())
-            || (player.get(ev.1).is_ok() && bullets.get(ev.0).is_err())
-        {
-            if cmd.get_entity(ev.0).is_none() || cmd.get_entity(ev.1).is_none() {
-                continue;
-            }
-            cmd.trigger(OnPlayerDamage);
+        let Ok((transform, is_large)) = asteroids.get(ev.asteroid) else {
+            continue;
+        };
+        let hurt = player.contains(ev.ship);
+        let claimed = if hurt {
+            hits.claim(&[ev.ship, ev.asteroid])
+        } else {
+            hits.claim(&[ev.asteroid])
+        };
+        if !claimed {
+            continue;
+        }
+        destroy_asteroid(&mut cmd, ev.asteroid, transform, is_large);
+        if hurt {
+            cmd.trigger_targets(OnPlayerDamage, ev.ship);
         }
     }
 }
 
-fn clear_player_grace(
+pub fn clear_player_grace(
     mut e: Query<(Entity, &mut PlayerGrace)>,
     mut cmd: Commands,
     time: Res<Time>,
@@ -325,44 +505,129 @@ fn clear_player_grace(
     });
 }
 
-fn player_grace(
-    _event: Trigger<OnPlayerDamage>,
+/// Minimum distance from any collider for a (re)spawned ship.
+const SAFE_SPAWN_DISTANCE: f32 = 200.0;
+const SAFE_SPAWN_ATTEMPTS: usize = 16;
+
+/// Picks the random point furthest from any collider, stopping early once one is safe enough.
+pub fn safe_spawn_position(tree: &NNTree, rng: &mut impl Rng) -> Vec2 {
+    let mut best = (Vec2::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0), 0.0);
+    for _ in 0..SAFE_SPAWN_ATTEMPTS {
+        let candidate = Vec2::new(
+            rng.gen_range(0.0..WINDOW_WIDTH),
+            rng.gen_range(0.0..WINDOW_HEIGHT),
+        );
+        let distance = tree
+            .nearest_neighbour(candidate)
+            .map_or(f32::MAX, |(pos, _)| pos.distance(candidate));
+        if distance > best.1 {
+            best = (candidate, distance);
+        }
+        if distance >= SAFE_SPAWN_DISTANCE {
+            break;
+        }
+    }
+    best.0
+}
+
+pub fn player_grace(
+    trigger: Trigger<OnPlayerDamage>,
     mut cmd: Commands,
-    player: Query<Entity, With<Player>>,
+    mut player: Query<
+        (&mut Lives, &mut Transform, &mut Velocity),
+        (Without<PlayerGrace>, Without<Downed>, Without<TeamPool>),
+    >,
+    pool: Query<&Lives, With<TeamPool>>,
+    mode: Res<GameMode>,
+    tree: Res<NNTree>,
+    mut rng: GlobalEntropy<RngType>,
 ) {
-    cmd.entity(player.single()).insert(PlayerGrace::default());
+    let Ok((mut lives, mut transform, mut velocity)) = player.get_mut(trigger.entity()) else {
+        return;
+    };
+    if mode.respawns() {
+        let position = safe_spawn_position(&tree, &mut **rng);
+        transform.translation = position.extend(0.0);
+        *velocity = Velocity { x: 0.0, y: 0.0 };
+        cmd.entity(trigger.entity())
+            .insert((PlayerGrace::default(), AngularVelocity::default()));
+        return;
+    }
+    if let Ok(pool) = pool.get_single() {
+        // Co-op ships go down until a revive draws a life from the team pool
+        *velocity = Velocity { x: 0.0, y: 0.0 };
+        let mut ship = cmd.entity(trigger.entity());
+        ship.remove::<CircleCollider>()
+            .insert(AngularVelocity::default());
+        if pool.0 <= 0 {
+            ship.insert(Eliminated);
+        } else {
+            ship.insert(Downed::default());
+        }
+        return;
+    }
+    lives.0 -= 1;
+    if lives.0 <= 0 {
+        cmd.entity(trigger.entity())
+            .insert(Eliminated)
+            .remove::<CircleCollider>();
+    } else {
+        cmd.entity(trigger.entity()).insert(PlayerGrace::default());
+    }
 }
 
-fn resolve_bullet_collisions(
-    mut e: EventReader<CollisionEvent>,
+pub fn add_score(
+    trigger: Trigger<OnScoreUpdate>,
+    mut score: Query<&mut Score>,
+    mode: Res<GameMode>,
+) {
+    if !mode.scores() {
+        return;
+    }
+    if let Ok(mut score) = score.get_mut(trigger.entity()) {
+        score.0 += trigger.event().0;
+    }
+}
+
+fn hide_eliminated(mut cmd: Commands, e: Query<Entity, Added<Eliminated>>) {
+    e.iter().for_each(|e| {
+        cmd.entity(e).insert(Visibility::Hidden);
+    });
+}
+
+/// Each projectile breaks one asteroid and each asteroid scores once, whatever else they touch.
+pub fn resolve_bullet_collisions(
+    mut e: EventReader<ProjectileHitAsteroid>,
     mut cmd: Commands,
-    asteroids: Query<(
-        &WrapTimeout,
-        &Transform,
-        Option<&crate::asteroid::LargeAsteroid>,
-    )>,
-    bullet: Query<(Entity, &ScoreMarker)>,
+    asteroids: Query<(&Transform, Has<LargeAsteroid>)>,
+    bullet: Query<(&Owner, &WeaponKind), With<ScoreMarker>>,
+    mut players: Query<(Entity, &PlayerId, &mut PlayerStats)>,
+    mode: Res<GameMode>,
+    rules: Res<GameRules>,
+    mut hits: ResMut<ResolvedHits>,
 ) {
     for ev in e.read() {
-        if let Ok((_, _, is_large)) = asteroids.get(ev.0) {
-            if bullet.get(ev.1).is_ok() {
-                if is_large.is_some() {
-                    cmd.trigger(OnScoreUpdate(25));
-                } else {
-                    cmd.trigger(OnScoreUpdate(10));
-                }
-                cmd.entity(bullet.get(ev.1).unwrap().0).despawn();
-            }
+        let (Ok((transform, is_large)), Ok((owner, kind))) =
+            (asteroids.get(ev.asteroid), bullet.get(ev.projectile))
+        else {
+            continue;
+        };
+        if !hits.claim(&[ev.projectile, ev.asteroid]) {
+            continue;
         }
-        if let Ok((_, _, is_large)) = asteroids.get(ev.1) {
-            if bullet.get(ev.0).is_ok() {
-                if is_large.is_some() {
-                    cmd.trigger(OnScoreUpdate(25));
-                } else {
-                    cmd.trigger(OnScoreUpdate(10));
-                }
-                cmd.entity(bullet.get(ev.0).unwrap().0).despawn();
+        if let Some((shooter, _, mut stats)) = players.iter_mut().find(|(_, id, _)| **id == owner.0)
+        {
+            stats.shots_hit += 1;
+            stats.asteroids_destroyed += 1;
+            // Asteroids are neutral hazards in versus, only kills score
+            if *mode != GameMode::Versus {
+                cmd.trigger_targets(OnScoreUpdate(rules.asteroid_score(is_large)), shooter);
             }
         }
+        // Beams fade on their own
+        if *kind != WeaponKind::Laser {
+            cmd.entity(ev.projectile).try_despawn();
+        }
+        destroy_asteroid(&mut cmd, ev.asteroid, transform, is_large);
     }
 }
@@ -0,0 +1,258 @@
+use std::fmt;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use lightyear::prelude::is_server;
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+
+/// Loads [`GameRules`] from the assets, reloading them when the file changes in dev builds.
+pub struct RulesPlugin;
+
+const RULES_PATH: &str = "game.rules.ron";
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<GameRules>()
+            .init_asset_loader::<GameRulesLoader>()
+            .add_systems(Startup, load_rules)
+            .add_systems(OnEnter(GameState::MainMenu), restore_rules)
+            // Clients play by the host's rules while in a session
+            .add_systems(
+                Update,
+                apply_rules.run_if(is_server.or(in_state(GameState::MainMenu))),
+            );
+    }
+}
+
+/// Tuning of the simulation. The active rules are a resource, replicated from the host.
+#[derive(Asset, Resource, TypePath, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GameRules {
+    pub physics: PhysicsProfile,
+    /// Velocity gained per second of thrust.
+    pub acceleration: f32,
+    /// Largest turn rate of a ship, in radians per second.
+    pub rotation_speed: f32,
+    pub max_velocity: f32,
+    /// Seconds between two shots.
+    pub shoot_timeout: f32,
+    pub projectile_speed: f32,
+    pub projectile_radius: f32,
+    pub projectile_range: ProjectileRange,
+    /// Projectiles a player may have on screen at once.
+    pub max_projectiles: u32,
+    pub ship_radius: f32,
+    pub lives: i8,
+    /// Largest velocity of a new asteroid along each axis.
+    pub asteroid_speed: f32,
+    pub small_asteroid_radius: f32,
+    pub large_asteroid_radius: f32,
+    /// How many times an asteroid may cross the screen edge.
+    pub asteroid_wraps: u8,
+    pub asteroid_physics: AsteroidPhysics,
+    /// Closing speed above which a ship breaks an [`AsteroidPhysics::Elastic`] asteroid
+    /// instead of bouncing off it, taking damage.
+    pub ram_speed: f32,
+    pub small_asteroid_score: u32,
+    pub large_asteroid_score: u32,
+    /// Points for hitting another ship in versus mode.
+    pub kill_score: u32,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            physics: PhysicsProfile::default(),
+            acceleration: 5.0,
+            rotation_speed: 8.0,
+            max_velocity: 3.0,
+            shoot_timeout: 0.5,
+            projectile_speed: 10.0,
+            projectile_radius: 10.0,
+            projectile_range: ProjectileRange::Seconds(1.0),
+            max_projectiles: 4,
+            ship_radius: 15.0,
+            lives: 3,
+            asteroid_speed: 3.0,
+            small_asteroid_radius: 20.0,
+            large_asteroid_radius: 40.0,
+            asteroid_wraps: 5,
+            asteroid_physics: AsteroidPhysics::default(),
+            ram_speed: 2.0,
+            small_asteroid_score: 10,
+            large_asteroid_score: 25,
+            kill_score: 100,
+        }
+    }
+}
+
+impl GameRules {
+    pub fn asteroid_radius(&self, is_large: bool) -> f32 {
+        if is_large {
+            self.large_asteroid_radius
+        } else {
+            self.small_asteroid_radius
+        }
+    }
+
+    pub fn asteroid_score(&self, is_large: bool) -> u32 {
+        if is_large {
+            self.large_asteroid_score
+        } else {
+            self.small_asteroid_score
+        }
+    }
+}
+
+/// How ships handle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum PhysicsProfile {
+    /// Ships stop quickly and turn instantly.
+    Arcade,
+    /// Ships drift until they brake and turn instantly, the handling the game always had.
+    #[default]
+    Classic,
+    /// Nothing slows a ship down but its own thrusters, turning included.
+    Newtonian,
+}
+
+/// How long a projectile flies before it disappears.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ProjectileRange {
+    Seconds(f32),
+    /// Pixels travelled, across screen edges too.
+    Distance(f32),
+}
+
+/// What happens when asteroids touch each other or a ship.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AsteroidPhysics {
+    /// Asteroids pass through each other and break on any ship they touch.
+    #[default]
+    Classic,
+    /// Asteroids bounce off each other and off ships, heavier ones pushing lighter ones.
+    Elastic,
+}
+
+/// Parameters of a [`PhysicsProfile`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Handling {
+    /// Share of its velocity a ship loses per second, applied continuously.
+    pub drag: f32,
+    /// Same as `drag`, for the turn rate.
+    pub angular_drag: f32,
+    /// Seconds for a ship to reach its full turn rate, zero turns instantly.
+    pub rotational_inertia: f32,
+    /// Acceleration of the retro thrusters relative to the main one.
+    pub reverse_thrust: f32,
+}
+
+impl PhysicsProfile {
+    pub fn handling(&self) -> Handling {
+        match self {
+            PhysicsProfile::Arcade => Handling {
+                drag: 2.0,
+                angular_drag: f32::INFINITY,
+                rotational_inertia: 0.0,
+                reverse_thrust: 1.0,
+            },
+            PhysicsProfile::Classic => Handling {
+                drag: 0.0,
+                angular_drag: f32::INFINITY,
+                rotational_inertia: 0.0,
+                reverse_thrust: 0.5,
+            },
+            PhysicsProfile::Newtonian => Handling {
+                drag: 0.0,
+                angular_drag: 0.0,
+                rotational_inertia: 0.3,
+                reverse_thrust: 0.5,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RulesError {
+    Io(std::io::Error),
+    Parse(ron::de::SpannedError),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Io(e) => write!(f, "Failed to read game rules: {}", e),
+            RulesError::Parse(e) => write!(f, "Failed to parse game rules: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RulesError {}
+
+#[derive(Default)]
+struct GameRulesLoader;
+
+impl AssetLoader for GameRulesLoader {
+    type Asset = GameRules;
+    type Settings = ();
+    type Error = RulesError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<GameRules, RulesError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RulesError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(RulesError::Parse)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct RulesHandle(Handle<GameRules>);
+
+fn load_rules(mut cmd: Commands, assets: Res<AssetServer>) {
+    cmd.insert_resource(RulesHandle(assets.load(RULES_PATH)));
+}
+
+fn apply_rules(
+    mut events: EventReader<AssetEvent<GameRules>>,
+    handle: Res<RulesHandle>,
+    assets: Res<Assets<GameRules>>,
+    mut rules: ResMut<GameRules>,
+) {
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        if let Some(loaded) = assets.get(&handle.0) {
+            info!("Applying game rules from {}", RULES_PATH);
+            *rules = loaded.clone();
+        }
+    }
+}
+
+/// Goes back to the local rules after playing by a host's.
+fn restore_rules(
+    handle: Option<Res<RulesHandle>>,
+    assets: Res<Assets<GameRules>>,
+    mut rules: ResMut<GameRules>,
+) {
+    // The main menu is first entered before the rules start loading
+    let Some(handle) = handle else {
+        return;
+    };
+    if let Some(loaded) = assets.get(&handle.0).filter(|it| **it != *rules) {
+        *rules = loaded.clone();
+    }
+}
@@ -0,0 +1,400 @@
+//! Wire shapes of serialized types, read from their `Deserialize` implementations.
+
+use std::collections::BTreeMap;
+
+use serde::de::value::{Error, U32Deserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// How `T` goes over the wire: its serde name, the names and shapes of its fields and the
+/// variants of every enum inside it, down to primitive types. Types with the same shape
+/// encode the same, and changing a field changes the shape. Fails for types that only
+/// self-describing formats can read, like untagged enums.
+pub fn shape_of<T: DeserializeOwned>() -> Result<String, Error> {
+    let mut trace = Trace::default();
+    // Every pass takes the first unseen variant of each enum, until no new one turns up
+    loop {
+        trace.progressed = false;
+        T::deserialize(Tracer(&mut trace))?;
+        if !trace.progressed {
+            break;
+        }
+    }
+    let mut shape = trace.last;
+    for (name, variants) in trace.enums {
+        let variants = variants
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect::<Vec<_>>();
+        shape += &format!(";{}={}", name, variants.join("|"));
+    }
+    Ok(shape)
+}
+
+#[derive(Default)]
+struct Trace {
+    /// Shape of the value traced last.
+    last: String,
+    /// Shapes of the elements of the sequences being traced, innermost last.
+    frames: Vec<Vec<String>>,
+    /// Shapes of the variants of every enum seen so far, by serde name.
+    enums: BTreeMap<&'static str, Vec<Option<String>>>,
+    /// Whether this pass saw a variant for the first time.
+    progressed: bool,
+}
+
+impl Trace {
+    fn elements<'de, V: Visitor<'de>>(
+        &mut self,
+        len: usize,
+        visitor: V,
+    ) -> Result<(V::Value, Vec<String>), Error> {
+        self.frames.push(Vec::new());
+        let value = visitor.visit_seq(Elements {
+            trace: &mut *self,
+            remaining: len,
+        });
+        let shapes = self.frames.pop().unwrap_or_default();
+        Ok((value?, shapes))
+    }
+}
+
+fn fields(names: &[&str], shapes: Vec<String>) -> String {
+    let fields = names
+        .iter()
+        .zip(shapes)
+        .map(|(name, shape)| format!("{}:{}", name, shape))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn tuple(shapes: Vec<String>) -> String {
+    format!("({})", shapes.join(","))
+}
+
+/// Deserializer that hands out placeholder values and records what was asked for.
+struct Tracer<'a>(&'a mut Trace);
+
+macro_rules! primitives {
+    ($($method:ident => $visit:ident($($value:expr)?)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.0.last = stringify!($method)["deserialize_".len()..].to_string();
+                visitor.$visit($($value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Tracer<'_> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        // Like bincode on the wire
+        false
+    }
+
+    primitives! {
+        deserialize_bool => visit_bool(false),
+        deserialize_i8 => visit_i8(0),
+        deserialize_i16 => visit_i16(0),
+        deserialize_i32 => visit_i32(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_u8 => visit_u8(0),
+        deserialize_u16 => visit_u16(0),
+        deserialize_u32 => visit_u32(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_f32 => visit_f32(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_char => visit_char('\0'),
+        deserialize_str => visit_str(""),
+        deserialize_string => visit_string(String::new()),
+        deserialize_bytes => visit_bytes(&[]),
+        deserialize_byte_buf => visit_byte_buf(Vec::new()),
+        deserialize_unit => visit_unit(),
+        deserialize_identifier => visit_u32(0),
+        deserialize_ignored_any => visit_unit(),
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        // Only self-describing formats can tell what comes next
+        Err(de::Error::custom(
+            "types without a fixed shape are not supported",
+        ))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = visitor.visit_some(Tracer(&mut *self.0))?;
+        self.0.last = format!("option<{}>", self.0.last);
+        Ok(value)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0.last = name.to_string();
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let value = visitor.visit_newtype_struct(Tracer(&mut *self.0))?;
+        self.0.last = format!("{}({})", name, self.0.last);
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let (value, shapes) = self.0.elements(1, visitor)?;
+        self.0.last = format!("seq<{}>", shapes.concat());
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let (value, shapes) = self.0.elements(len, visitor)?;
+        self.0.last = tuple(shapes);
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (value, shapes) = self.0.elements(len, visitor)?;
+        self.0.last = format!("{}{}", name, tuple(shapes));
+        Ok(value)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.0.frames.push(Vec::new());
+        let value = visitor.visit_map(Entries {
+            trace: &mut *self.0,
+            remaining: 1,
+        });
+        let shapes = self.0.frames.pop().unwrap_or_default();
+        self.0.last = format!("map<{}>", shapes.join(","));
+        value
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        names: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (value, shapes) = self.0.elements(names.len(), visitor)?;
+        self.0.last = format!("{}{}", name, fields(names, shapes));
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let seen = self
+            .0
+            .enums
+            .entry(name)
+            .or_insert_with(|| vec![None; variants.len()]);
+        let index = seen.iter().position(Option::is_none).unwrap_or(0);
+        let value = visitor.visit_enum(Variant {
+            trace: &mut *self.0,
+            index,
+        })?;
+        let seen = &mut self.0.enums.get_mut(name).expect("enum was seen")[index];
+        if seen.is_none() {
+            *seen = Some(format!("{}{}", variants[index], self.0.last));
+            self.0.progressed = true;
+        }
+        // Enums are listed once after the whole shape
+        self.0.last = name.to_string();
+        Ok(value)
+    }
+}
+
+/// Elements of a sequence, tuple or struct, in order.
+struct Elements<'a> {
+    trace: &'a mut Trace,
+    remaining: usize,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let value = seed.deserialize(Tracer(&mut *self.trace))?;
+        let shape = std::mem::take(&mut self.trace.last);
+        if let Some(frame) = self.trace.frames.last_mut() {
+            frame.push(shape);
+        }
+        Ok(Some(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// A single key and value of a map.
+struct Entries<'a> {
+    trace: &'a mut Trace,
+    remaining: usize,
+}
+
+impl Entries<'_> {
+    fn push_shape(&mut self) {
+        let shape = std::mem::take(&mut self.trace.last);
+        if let Some(frame) = self.trace.frames.last_mut() {
+            frame.push(shape);
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for Entries<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let key = seed.deserialize(Tracer(&mut *self.trace))?;
+        self.push_shape();
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = seed.deserialize(Tracer(&mut *self.trace))?;
+        self.push_shape();
+        Ok(value)
+    }
+}
+
+/// The variant of an enum picked for this pass, leaves the shape of its fields behind.
+struct Variant<'a> {
+    trace: &'a mut Trace,
+    index: usize,
+}
+
+impl<'de> EnumAccess<'de> for Variant<'_> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<(T::Value, Self), Error> {
+        let index: U32Deserializer<Error> = (self.index as u32).into_deserializer();
+        Ok((seed.deserialize(index)?, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        self.trace.last = String::new();
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let value = seed.deserialize(Tracer(&mut *self.trace))?;
+        self.trace.last = format!("({})", self.trace.last);
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let (value, shapes) = self.trace.elements(len, visitor)?;
+        self.trace.last = tuple(shapes);
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        names: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (value, shapes) = self.trace.elements(names.len(), visitor)?;
+        self.trace.last = fields(names, shapes);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::schema::shape_of;
+
+    mod before {
+        #[derive(serde::Deserialize)]
+        #[allow(dead_code)]
+        pub struct Rules {
+            pub lives: i8,
+        }
+    }
+
+    mod after {
+        #[derive(serde::Deserialize)]
+        #[allow(dead_code)]
+        pub struct Rules {
+            pub lives: i8,
+            pub max_projectiles: u32,
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Range {
+        Seconds(f32),
+        Distance { pixels: f32 },
+        Forever,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum Either {
+        Seconds(f32),
+        Name(String),
+    }
+
+    #[test]
+    fn shapes_list_fields_and_every_variant() {
+        assert_eq!(shape_of::<before::Rules>().unwrap(), "Rules{lives:i8}");
+        assert_eq!(
+            shape_of::<Vec<Option<Range>>>().unwrap(),
+            "seq<option<Range>>;Range=Seconds(f32)|Distance{pixels:f32}|Forever"
+        );
+    }
+
+    #[test]
+    fn new_fields_change_the_shape() {
+        assert_ne!(
+            shape_of::<before::Rules>().unwrap(),
+            shape_of::<after::Rules>().unwrap()
+        );
+    }
+
+    #[test]
+    fn self_describing_types_have_no_shape() {
+        assert!(shape_of::<Either>().is_err());
+    }
+}
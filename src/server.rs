@@ -1,47 +1,89 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
 
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_egui::{EguiContext, egui};
+use bevy_rand::global::GlobalEntropy;
 use egui::Align2;
 use lightyear::prelude::server::Replicate;
 use lightyear::prelude::*;
-use lightyear::server::events::{ConnectEvent, DisconnectEvent};
+use lightyear::server::events::{DisconnectEvent, MessageEvent};
 use rust_i18n::t;
 use server::{
     InputEvent, IoConfig, NetConfig, NetcodeConfig, ServerCommands, ServerConfig, ServerPlugins,
     ServerTransport,
 };
+use strum::IntoEnumIterator;
 
-use crate::player::{PlayerAction, PlayerId, PlayerSpawner, ProjectileSprite, ScoreMarker};
-use crate::shared::{DefaultChannel, StartGameMessage};
+use crate::auth::{SecuritySettings, TokenEndpoint, generate_key};
+use crate::bot::{Bot, BotDifficulty};
+use crate::chat::{ChatLog, SendChat, sanitize};
+use crate::discovery::{Announcement, bind_host_socket, decode_query};
+use crate::game_mode::{GameMode, MatchSettings, OnKill, TeamPool};
+use crate::network_debug::{NetworkSettings, send_probes};
+use crate::player::{
+    PlayerGrace, PlayerId, PlayerName, PlayerSpawner, ShipInput, player_state, safe_spawn_position,
+};
+use crate::rules::GameRules;
+use crate::shared::{
+    ChatChannel, ChatMessage, DefaultChannel, HandshakeMessage, KillFeedMessage, MatchResults,
+    PlayerInfoMessage, ProtocolVersion, RejectMessage, RejectReason, ReturnToLobbyMessage,
+    StartGameMessage, WelcomeMessage,
+};
+use crate::simulation::{Headless, Lifetime, ShipAction, apply_ship_actions};
+use crate::spectator::LocalPlayer;
+use crate::weapon::{WeaponKind, Weapons};
 use crate::{
-    ACC_SPEED, CircleCollider, CleanupOnGameOver, MAX_VELOCITY, PROJECTILE_SPEED, ROTATION_SPEED,
-    Velocity, WINDOW_HEIGHT, WINDOW_WIDTH, WrapTimeout,
+    CleanupOnGameOver, Lives, NNTree, RngType, Velocity, WINDOW_HEIGHT, WINDOW_WIDTH, WrapTimeout,
 };
 use crate::{
-    GameState, HostGame, SERVER_ADDR, ServerAddress,
-    shared::{self, SERVER_REPLICATION_INTERVAL},
+    GameState, HostGame, Nickname, ServerAddress,
+    shared::{self, FIXED_TIMESTEP_HZ, NETCODE_PROTOCOL_ID, SERVER_REPLICATION_INTERVAL},
 };
 
 pub struct ServerPlugin;
 
-fn net_config(address: SocketAddr) -> NetConfig {
+/// Maximum number of chat messages a client may send within [`CHAT_RATE_WINDOW`].
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(5);
+/// Time a rejected client gets to receive the reason before it is dropped.
+const REJECT_GRACE: Duration = Duration::from_secs(1);
+/// Invalid inputs a client may send within [`INPUT_VIOLATION_WINDOW`] before it is reported.
+const INPUT_VIOLATION_LIMIT: usize = 10;
+const INPUT_VIOLATION_WINDOW: Duration = Duration::from_secs(5);
+/// Bots get player ids from here up, far away from the random netcode client ids.
+const BOT_ID_BASE: u64 = u64::MAX - u16::MAX as u64;
+
+fn net_config(
+    address: SocketAddr,
+    key: Key,
+    conditioner: Option<LinkConditionerConfig>,
+) -> NetConfig {
     let io = IoConfig {
         transport: ServerTransport::UdpSocket(address),
+        conditioner,
         ..default()
     };
     NetConfig::Netcode {
         io,
-        config: NetcodeConfig::default(),
+        config: NetcodeConfig::default()
+            .with_key(key)
+            .with_protocol_id(NETCODE_PROTOCOL_ID),
     }
 }
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
+        // Transports are added by `on_host_game` once the address and key are known
+        let mode = if app.world().contains_resource::<Headless>() {
+            Mode::Separate
+        } else {
+            Mode::HostServer
+        };
         let config = ServerConfig {
-            shared: shared::shared_config(),
-            net: vec![net_config(SERVER_ADDR)],
+            shared: shared::shared_config(mode),
             replication: ReplicationConfig {
                 send_interval: SERVER_REPLICATION_INTERVAL,
                 ..default()
@@ -51,135 +93,525 @@ impl Plugin for ServerPlugin {
         app.add_plugins(ServerPlugins::new(config))
             .add_observer(on_host_game)
             .add_observer(on_start_game)
+            .add_observer(on_rematch)
+            .add_observer(broadcast_kill)
             .init_resource::<ConnectedPlayers>()
+            .init_resource::<ChatRateLimiter>()
+            .init_resource::<RejectedClients>()
+            .init_resource::<InputGuard>()
+            .init_resource::<DiscoveryHost>()
             .add_systems(
                 OnEnter(GameState::Playing),
-                spawn_player_for_each_connection,
+                (
+                    spawn_player_for_each_connection,
+                    spawn_team_pool.run_if(resource_equals(GameMode::Coop)),
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                handle_player_inputs
+                    .before(apply_ship_actions)
+                    .run_if(is_server),
             )
-            .add_systems(FixedUpdate, handle_player_inputs.run_if(is_server))
-            .add_observer(shoot_projectile)
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                send_match_results.run_if(is_server),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), close_session)
             .add_systems(
                 Update,
                 (
-                    (
-                        lobby_menu,
-                        handle_connections,
-                        handle_disconnections,
-                        lobby_menu,
-                    )
+                    (lobby_menu, lobby_menu, bots_menu)
                         .run_if(in_state(GameState::Lobby).and(is_server)),
-                    update_server_config.run_if(in_state(GameState::MainMenu)),
+                    (handle_handshakes, drop_rejected, handle_disconnections).run_if(is_server),
+                    (spawn_drop_in_players, replicate_spawned)
+                        .run_if(in_state(GameState::Playing).and(is_server)),
+                    handle_player_info.run_if(is_server),
+                    broadcast_rules.run_if(is_server.and(resource_changed::<GameRules>)),
+                    (host_chat, relay_chat).run_if(is_server),
+                    answer_discovery.run_if(is_server.and(not(resource_exists::<LocalSession>))),
+                    send_probes.run_if(is_server),
+                    results_menu.run_if(in_state(GameState::GameOver).and(is_server)),
+                    collect_authenticated.run_if(resource_exists::<TokenEndpoint>),
                 ),
             );
     }
 }
 
 #[derive(Resource, Default)]
-struct ConnectedPlayers {
+pub struct ConnectedPlayers {
     players: Vec<u64>,
+    names: HashMap<u64, String>,
+    /// Clients that joined with a connect token from our endpoint.
+    authenticated: HashSet<u64>,
+    /// Clients that joined a match in progress and still need a ship.
+    drop_ins: Vec<u64>,
+    /// Clients that joined a match in progress and watch it, they play from the next one.
+    spectators: Vec<u64>,
+    /// Whether clients joining a match in progress play right away instead of spectating.
+    allow_drop_in: bool,
+    bots: Vec<(u64, BotDifficulty)>,
+}
+
+impl ConnectedPlayers {
+    fn name(&self, id: u64) -> String {
+        self.names
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("Player {}", id))
+    }
+
+    pub fn bots(&self) -> usize {
+        self.bots.len()
+    }
+
+    pub fn add_bot(&mut self, difficulty: BotDifficulty) {
+        let Some(id) = (BOT_ID_BASE..).find(|id| !self.bots.iter().any(|(bot, _)| bot == id))
+        else {
+            return;
+        };
+        self.names.insert(
+            id,
+            t!(
+                "bot",
+                number = id - BOT_ID_BASE + 1,
+                difficulty = difficulty.name()
+            )
+            .to_string(),
+        );
+        self.bots.push((id, difficulty));
+    }
+
+    fn remove_bot(&mut self, id: u64) {
+        self.bots.retain(|(bot, _)| *bot != id);
+        self.names.remove(&id);
+    }
+}
+
+fn spawn_ship(
+    cmd: &mut Commands,
+    spawner: Option<&PlayerSpawner>,
+    players: &ConnectedPlayers,
+    rules: &GameRules,
+    settings: &MatchSettings,
+    player: u64,
+    position: Vec2,
+) -> Entity {
+    let mut ship = cmd.spawn((
+        player_state(player, players.name(player), rules, &settings.loadout),
+        Transform::from_xyz(position.x, position.y, 0.0),
+        Velocity { x: 0.0, y: 0.0 },
+        Replicate::default(),
+    ));
+    // Headless servers draw nothing
+    if let Some(spawner) = spawner {
+        ship.insert(spawner.player_client());
+    }
+    ship.id()
 }
 
 fn spawn_player_for_each_connection(
     mut cmd: Commands,
     players: Res<ConnectedPlayers>,
-    spawner: Single<&PlayerSpawner>,
+    spawner: Option<Single<&PlayerSpawner>>,
+    rules: Res<GameRules>,
+    settings: Res<MatchSettings>,
 ) {
+    let spawner = spawner.as_deref().copied();
+    let center = Vec2::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0);
     for player in &players.players {
-        cmd.spawn((
-            spawner.player_client(),
-            PlayerId(*player),
-            Transform::from_xyz(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0, 0.0),
-            Velocity { x: 0.0, y: 0.0 },
-            Replicate::default(),
-        ));
+        spawn_ship(
+            &mut cmd, spawner, &players, &rules, &settings, *player, center,
+        );
+    }
+    for (bot, difficulty) in &players.bots {
+        let ship = spawn_ship(&mut cmd, spawner, &players, &rules, &settings, *bot, center);
+        cmd.entity(ship).insert(Bot::new(*difficulty));
+    }
+}
+
+/// Co-op teams share the lives every player would otherwise have on their own.
+fn spawn_team_pool(
+    mut cmd: Commands,
+    players: Res<ConnectedPlayers>,
+    rules: Res<GameRules>,
+    headless: Option<Res<Headless>>,
+) {
+    // The host's ship is not part of the connected clients, bots share the pool too.
+    // Headless servers have no ship of their own.
+    let host = usize::from(headless.is_none());
+    let team_size = (players.players.len() + players.bots() + host).min(i8::MAX as usize) as i8;
+    cmd.spawn((
+        TeamPool,
+        Lives(rules.lives.saturating_mul(team_size)),
+        CleanupOnGameOver,
+        Replicate::default(),
+    ));
+}
+
+fn spawn_drop_in_players(
+    mut cmd: Commands,
+    mut players: ResMut<ConnectedPlayers>,
+    spawner: Option<Single<&PlayerSpawner>>,
+    tree: Res<NNTree>,
+    mut rng: GlobalEntropy<RngType>,
+    rules: Res<GameRules>,
+    settings: Res<MatchSettings>,
+) {
+    for player in std::mem::take(&mut players.drop_ins) {
+        let position = safe_spawn_position(&tree, &mut **rng);
+        let ship = spawn_ship(
+            &mut cmd,
+            spawner.as_deref().copied(),
+            &players,
+            &rules,
+            &settings,
+            player,
+            position,
+        );
+        cmd.entity(ship).insert(PlayerGrace::default());
     }
 }
 
+/// Hands client inputs over to the simulation, dropping the ones no honest client sends.
 fn handle_player_inputs(
-    mut inputs: EventReader<InputEvent<PlayerAction>>,
-    mut players: Query<(&PlayerId, Entity, &mut Transform, &mut Velocity)>,
+    mut inputs: EventReader<InputEvent<ShipInput>>,
+    mut actions: EventWriter<ShipAction>,
+    mut guard: ResMut<InputGuard>,
+    ships: Query<(&PlayerId, &Weapons)>,
     mut cmd: Commands,
-    time: Res<Time>,
+    security: Res<SecuritySettings>,
+    rules: Res<GameRules>,
+    tick_manager: Res<TickManager>,
+    time: Res<Time<Real>>,
 ) {
+    let tick = tick_manager.tick();
     for input in inputs.read() {
-        if input.input().is_none() {
+        let Some(received) = *input.input() else {
             continue;
+        };
+        let client = input.from();
+        let id = client.to_bits();
+        let malformed = !received.is_valid();
+        // Clients hold their shots back by the cooldown of the weapon they fire, which is
+        // the one selected once this input's switch is applied
+        let kind = ships
+            .iter()
+            .find(|(ship, _)| ship.0 == id)
+            .map(|(_, weapons)| {
+                let mut weapons = weapons.clone();
+                weapons.hold_switch(received.switch_weapon);
+                weapons.current().kind
+            })
+            .unwrap_or_default();
+        let early_shot =
+            received.shoot && !malformed && !guard.accept_shot(id, tick, shot_ticks(kind, &rules));
+        if !malformed {
+            let input = ShipInput {
+                shoot: received.shoot && !early_shot,
+                ..received
+            };
+            actions.send(ShipAction { player: id, input });
         }
-        let action_state = input.input().as_ref().unwrap();
-        let player = players
-            .iter_mut()
-            .find(|it| it.0.0 == input.from().to_bits());
-        if let Some((_, e, mut transform, mut velocity)) = player {
-            let direction = transform.rotation * Vec3::Y;
-            let translation = direction * ACC_SPEED * time.delta().as_secs_f32();
-            match action_state {
-                PlayerAction::Forward => velocity.update(translation.xy()),
-                PlayerAction::Shoot => cmd.trigger(NetworkPlayerShoot(e)),
-                PlayerAction::Rotate(sign) => {
-                    transform.rotate_z(-1.0 * *sign as f32 * ROTATION_SPEED * time.delta_secs())
-                }
-                PlayerAction::None => (),
+        if !malformed && !early_shot {
+            continue;
+        }
+        debug!("Dropping invalid input {:?} from {}", received, id);
+        if guard.report(id, time.elapsed()) {
+            warn!("Client {} sends input faster than the game allows", id);
+            if security.kick_cheaters {
+                info!("Disconnecting client {}", id);
+                cmd.disconnect(client);
             }
-            velocity.max(MAX_VELOCITY);
         }
     }
 }
 
-#[derive(Event)]
-struct NetworkPlayerShoot(Entity);
-
-fn shoot_projectile(
-    trigger: Trigger<NetworkPlayerShoot>,
-    players: Query<(&Transform, &Velocity), With<PlayerId>>,
+/// Replicates the asteroids and projectiles the simulation spawns.
+fn replicate_spawned(
     mut cmd: Commands,
-    material: Res<ProjectileSprite>,
+    spawned: Query<Entity, Or<(Added<WrapTimeout>, Added<Lifetime>)>>,
 ) {
-    let id = trigger.event().0;
-    let (transform, velocity) = players.get(id).unwrap();
-    let direction = transform.rotation * Vec3::Y;
-    cmd.spawn((
-        Transform::from_translation(transform.translation),
-        Mesh2d(material.1.clone()),
-        MeshMaterial2d(material.0.clone()),
-        Velocity {
-            x: velocity.x + direction.x * PROJECTILE_SPEED,
-            y: velocity.y + direction.y * PROJECTILE_SPEED,
-        },
-        WrapTimeout(1),
-        CircleCollider::new(10.0),
-        ScoreMarker,
-        CleanupOnGameOver,
-        Replicate::default(),
-    ));
+    for e in &spawned {
+        cmd.entity(e).insert(Replicate::default());
+    }
 }
 
-fn handle_connections(
-    mut connections: EventReader<ConnectEvent>,
+/// Admits clients into the lobby once they prove they speak the same protocol.
+/// Clients joining a match in progress spectate until the next one starts.
+fn handle_handshakes(
+    mut messages: EventReader<MessageEvent<HandshakeMessage>>,
     mut players: ResMut<ConnectedPlayers>,
+    mut server: ResMut<server::ConnectionManager>,
+    protocol: Res<ProtocolVersion>,
+    state: Res<State<GameState>>,
+    mode: Res<GameMode>,
+    settings: Res<MatchSettings>,
+    mut rejected: ResMut<RejectedClients>,
+    time: Res<Time<Real>>,
+    rules: Res<GameRules>,
 ) {
-    for connection in connections.read() {
-        players.players.push(connection.client_id.to_bits());
+    for message in messages.read() {
+        let client = message.from();
+        let version = message.message().version;
+        if version != protocol.0 {
+            warn!(
+                "Rejecting client {:?}: protocol {:016x} does not match {:016x}",
+                client, version, protocol.0
+            );
+            server
+                .send_message::<DefaultChannel, RejectMessage>(
+                    client,
+                    &RejectMessage(RejectReason::IncompatibleVersion {
+                        server: protocol.0,
+                        client: version,
+                    }),
+                )
+                .unwrap_or_else(|e| {
+                    error!("Failed to send reject message: {}", e);
+                });
+            rejected.0.push((client, time.elapsed()));
+            continue;
+        }
+        let id = client.to_bits();
+        let playing = *state.get() == GameState::Playing;
+        let spectating = playing && !players.allow_drop_in;
+        if !players.players.contains(&id) && !players.spectators.contains(&id) {
+            if spectating {
+                players.spectators.push(id);
+            } else {
+                players.players.push(id);
+                if playing {
+                    players.drop_ins.push(id);
+                }
+            }
+        }
+        // The rules were loaded long before this client joined, so it gets them now
+        // and is already playing by them when it is welcomed
+        server
+            .send_message::<DefaultChannel, GameRules>(client, &rules)
+            .unwrap_or_else(|e| {
+                error!("Failed to send game rules: {}", e);
+            });
+        server
+            .send_message::<DefaultChannel, WelcomeMessage>(
+                client,
+                &WelcomeMessage {
+                    id: client.to_bits(),
+                    spectating,
+                },
+            )
+            .unwrap_or_else(|e| {
+                error!("Failed to send welcome message: {}", e);
+            });
+        if playing {
+            server
+                .send_message::<DefaultChannel, StartGameMessage>(
+                    client,
+                    &StartGameMessage {
+                        mode: *mode,
+                        settings: settings.clone(),
+                    },
+                )
+                .unwrap_or_else(|e| {
+                    error!("Failed to send start game message: {}", e);
+                });
+        }
     }
 }
 
+/// Clients sent a [`RejectMessage`], with the time it was sent.
+#[derive(Resource, Default)]
+struct RejectedClients(Vec<(ClientId, Duration)>);
+
+/// Drops rejected clients even if they ignore the reject and stay connected.
+fn drop_rejected(mut rejected: ResMut<RejectedClients>, mut cmd: Commands, time: Res<Time<Real>>) {
+    let now = time.elapsed();
+    rejected.0.retain(|(client, at)| {
+        if now.saturating_sub(*at) < REJECT_GRACE {
+            return true;
+        }
+        info!("Disconnecting rejected client {:?}", client);
+        cmd.disconnect(*client);
+        false
+    });
+}
+
 fn handle_disconnections(
     mut connections: EventReader<DisconnectEvent>,
     mut players: ResMut<ConnectedPlayers>,
+    mut limiter: ResMut<ChatRateLimiter>,
+    mut guard: ResMut<InputGuard>,
+    mut cmd: Commands,
+    ships: Query<(Entity, &PlayerId)>,
 ) {
     for connection in connections.read() {
-        players
-            .players
-            .retain(|&id| id != connection.client_id.to_bits());
+        let id = connection.client_id.to_bits();
+        ships
+            .iter()
+            .filter(|(_, player)| player.0 == id)
+            .for_each(|(ship, _)| cmd.entity(ship).despawn_recursive());
+        players.players.retain(|&it| it != id);
+        players.drop_ins.retain(|&it| it != id);
+        players.spectators.retain(|&it| it != id);
+        players.names.remove(&id);
+        players.authenticated.remove(&id);
+        limiter.history.remove(&id);
+        guard.violations.remove(&id);
+        guard.last_shot.remove(&id);
+    }
+}
+
+fn handle_player_info(
+    mut messages: EventReader<MessageEvent<PlayerInfoMessage>>,
+    mut players: ResMut<ConnectedPlayers>,
+    mut ships: Query<(&PlayerId, &mut PlayerName)>,
+) {
+    for message in messages.read() {
+        let name = message.message().name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        let id = message.from().to_bits();
+        players.names.insert(id, name.to_string());
+        // Ships of drop-in players can be spawned before their name arrives
+        ships
+            .iter_mut()
+            .filter(|(player, _)| player.0 == id)
+            .for_each(|(_, mut ship)| ship.0 = name.to_string());
+    }
+}
+
+/// Timestamps of recent chat messages per client.
+#[derive(Resource, Default)]
+struct ChatRateLimiter {
+    history: HashMap<u64, VecDeque<Duration>>,
+}
+
+impl ChatRateLimiter {
+    fn allow(&mut self, id: u64, now: Duration) -> bool {
+        let history = self.history.entry(id).or_default();
+        while history
+            .front()
+            .is_some_and(|sent| now.saturating_sub(*sent) > CHAT_RATE_WINDOW)
+        {
+            history.pop_front();
+        }
+        if history.len() >= CHAT_RATE_LIMIT {
+            return false;
+        }
+        history.push_back(now);
+        true
+    }
+}
+
+/// Fewest ticks an honest client waits after a shot of `kind`.
+fn shot_ticks(kind: WeaponKind, rules: &GameRules) -> i16 {
+    (kind.cooldown(rules) * FIXED_TIMESTEP_HZ as f32).ceil() as i16
+}
+
+/// Timestamps of recent invalid inputs and the tick and cooldown of the last accepted shot
+/// per client.
+#[derive(Resource, Default)]
+struct InputGuard {
+    violations: HashMap<u64, VecDeque<Duration>>,
+    last_shot: HashMap<u64, (Tick, i16)>,
+}
+
+impl InputGuard {
+    /// Whether a shot on `tick` waits out the cooldown of the last one, remembered along
+    /// with its own `cooldown` if so.
+    fn accept_shot(&mut self, id: u64, tick: Tick, cooldown: i16) -> bool {
+        if self
+            .last_shot
+            .get(&id)
+            .is_some_and(|(last, wait)| (0..*wait).contains(&(tick - *last)))
+        {
+            return false;
+        }
+        self.last_shot.insert(id, (tick, cooldown));
+        true
+    }
+
+    /// Records an invalid input, true once the client crosses the limit.
+    fn report(&mut self, id: u64, now: Duration) -> bool {
+        let violations = self.violations.entry(id).or_default();
+        while violations
+            .front()
+            .is_some_and(|sent| now.saturating_sub(*sent) > INPUT_VIOLATION_WINDOW)
+        {
+            violations.pop_front();
+        }
+        violations.push_back(now);
+        violations.len() == INPUT_VIOLATION_LIMIT
+    }
+}
+
+fn broadcast_chat(server: &mut server::ConnectionManager, log: &mut ChatLog, message: ChatMessage) {
+    server
+        .send_message_to_target::<ChatChannel, ChatMessage>(&message, NetworkTarget::All)
+        .unwrap_or_else(|e| {
+            error!("Failed to send chat message: {}", e);
+        });
+    log.push(message.sender, message.text);
+}
+
+fn host_chat(
+    mut events: EventReader<SendChat>,
+    mut server: ResMut<server::ConnectionManager>,
+    mut log: ResMut<ChatLog>,
+    nickname: Res<Nickname>,
+) {
+    for event in events.read() {
+        broadcast_chat(
+            &mut server,
+            &mut log,
+            ChatMessage {
+                sender: nickname.0.clone(),
+                text: event.0.clone(),
+            },
+        );
+    }
+}
+
+fn relay_chat(
+    mut messages: EventReader<MessageEvent<ChatMessage>>,
+    mut server: ResMut<server::ConnectionManager>,
+    mut log: ResMut<ChatLog>,
+    mut limiter: ResMut<ChatRateLimiter>,
+    players: Res<ConnectedPlayers>,
+    time: Res<Time<Real>>,
+) {
+    for message in messages.read() {
+        let id = message.from().to_bits();
+        if !limiter.allow(id, time.elapsed()) {
+            warn!("Dropping chat message from {}: rate limit exceeded", id);
+            continue;
+        }
+        let text = sanitize(&message.message().text);
+        if text.is_empty() {
+            continue;
+        }
+        broadcast_chat(
+            &mut server,
+            &mut log,
+            ChatMessage {
+                sender: players.name(id),
+                text,
+            },
+        );
     }
 }
 
 #[derive(Event)]
-struct StartGame;
+pub struct StartGame;
 
 fn lobby_menu(
     mut cmd: Commands,
     mut ctx: Query<&mut EguiContext, With<PrimaryWindow>>,
-    players: Res<ConnectedPlayers>,
+    mut players: ResMut<ConnectedPlayers>,
+    mut mode: ResMut<GameMode>,
+    mut settings: ResMut<MatchSettings>,
 ) {
     let Ok(mut ctx) = ctx.get_single_mut() else {
         return;
@@ -191,23 +623,114 @@ fn lobby_menu(
         .pivot(Align2::CENTER_CENTER)
         .current_pos(egui::Pos2::new(rect.max.x / 2.0, rect.max.y / 2.0))
         .show(ctx.get_mut(), |ui| {
-            for client in &players.players {
-                ui.label(format!("Player {}", client));
+            for client in players.players.iter().chain(&players.spectators) {
+                ui.horizontal(|ui| {
+                    ui.label(players.name(*client));
+                    if players.authenticated.contains(client) {
+                        ui.label(t!("lobby.authenticated"));
+                    } else {
+                        ui.weak(t!("lobby.unauthenticated"));
+                    }
+                });
             }
+            ui.separator();
+            ui.checkbox(&mut players.allow_drop_in, t!("lobby.drop_in"));
+            egui::ComboBox::from_label(t!("mode"))
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    GameMode::iter().for_each(|it| {
+                        ui.selectable_value(&mut *mode, it, it.name());
+                    });
+                });
+            if *mode == GameMode::Versus {
+                ui.add(
+                    egui::Slider::new(&mut settings.frag_limit, 1..=50)
+                        .text(t!("mode.frag_limit.label")),
+                );
+            }
+            if mode.has_time_limit() {
+                ui.add(
+                    egui::Slider::new(&mut settings.time_limit_minutes, 1..=30)
+                        .text(t!("mode.time_limit.label")),
+                );
+            }
+            ui.label(t!("lobby.loadout"));
+            ui.horizontal(|ui| {
+                // The standard gun is always carried
+                for kind in WeaponKind::iter().skip(1) {
+                    let mut carried = settings.loadout.contains(&kind);
+                    if ui.checkbox(&mut carried, kind.name()).changed() {
+                        if carried {
+                            settings.loadout.push(kind);
+                        } else {
+                            settings.loadout.retain(|it| *it != kind);
+                        }
+                    }
+                }
+            });
             if ui.button(t!("play")).clicked() {
                 cmd.trigger(StartGame);
             }
         });
 }
 
+fn bots_menu(
+    mut ctx: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut players: ResMut<ConnectedPlayers>,
+    mut difficulty: Local<BotDifficulty>,
+) {
+    let Ok(mut ctx) = ctx.get_single_mut() else {
+        return;
+    };
+    let rect = ctx.get_mut().input(|i: &egui::InputState| i.screen_rect());
+    egui::Window::new(t!("bots"))
+        .pivot(Align2::CENTER_TOP)
+        .current_pos(egui::Pos2::new(rect.max.x / 2.0, rect.max.y * 0.75))
+        .show(ctx.get_mut(), |ui| {
+            let mut removed = None;
+            for (bot, _) in &players.bots {
+                ui.horizontal(|ui| {
+                    ui.label(players.name(*bot));
+                    if ui.button(t!("bot.remove")).clicked() {
+                        removed = Some(*bot);
+                    }
+                });
+            }
+            if let Some(bot) = removed {
+                players.remove_bot(bot);
+            }
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("bot_difficulty")
+                    .selected_text(difficulty.name())
+                    .show_ui(ui, |ui| {
+                        BotDifficulty::iter().for_each(|it| {
+                            ui.selectable_value(&mut *difficulty, it, it.name());
+                        });
+                    });
+                if ui.button(t!("bot.add")).clicked() {
+                    players.add_bot(*difficulty);
+                }
+            });
+        });
+}
+
 fn on_start_game(
     _trigger: Trigger<StartGame>,
     mut server: ResMut<server::ConnectionManager>,
     mut state: ResMut<NextState<GameState>>,
+    mut players: ResMut<ConnectedPlayers>,
+    mode: Res<GameMode>,
+    settings: Res<MatchSettings>,
 ) {
+    // Clients that watched the last match play in this one
+    let players = &mut *players;
+    players.players.append(&mut players.spectators);
     server
         .send_message_to_target::<DefaultChannel, StartGameMessage>(
-            &StartGameMessage,
+            &StartGameMessage {
+                mode: *mode,
+                settings: settings.clone(),
+            },
             NetworkTarget::All,
         )
         .unwrap_or_else(|e| {
@@ -216,17 +739,141 @@ fn on_start_game(
     state.set(GameState::Playing);
 }
 
-fn on_host_game(
-    _trigger: Trigger<HostGame>,
-    mut cmd: Commands,
+/// Clients play by the host's rules, including changes reloaded mid match. Clients joining
+/// later get the current rules with their handshake, see [`handle_handshakes`].
+fn broadcast_rules(mut server: ResMut<server::ConnectionManager>, rules: Res<GameRules>) {
+    server
+        .send_message_to_target::<DefaultChannel, GameRules>(&rules, NetworkTarget::All)
+        .unwrap_or_else(|e| {
+            error!("Failed to send game rules: {}", e);
+        });
+}
+
+fn broadcast_kill(trigger: Trigger<OnKill>, mut server: ResMut<server::ConnectionManager>) {
+    server
+        .send_message_to_target::<DefaultChannel, KillFeedMessage>(
+            &KillFeedMessage(trigger.event().clone()),
+            NetworkTarget::All,
+        )
+        .unwrap_or_else(|e| {
+            error!("Failed to send kill feed message: {}", e);
+        });
+}
+
+fn send_match_results(
+    mut server: ResMut<server::ConnectionManager>,
+    results: Option<Res<MatchResults>>,
+) {
+    let Some(results) = results else {
+        return;
+    };
+    server
+        .send_message_to_target::<DefaultChannel, MatchResults>(&results, NetworkTarget::All)
+        .unwrap_or_else(|e| {
+            error!("Failed to send match results: {}", e);
+        });
+}
+
+#[derive(Event)]
+pub struct Rematch;
+
+fn results_menu(mut cmd: Commands, mut ctx: Query<&mut EguiContext, With<PrimaryWindow>>) {
+    let Ok(mut ctx) = ctx.get_single_mut() else {
+        return;
+    };
+    let rect = ctx.get_mut().input(|i: &egui::InputState| i.screen_rect());
+    egui::Window::new("Host")
+        .pivot(Align2::CENTER_TOP)
+        .current_pos(egui::Pos2::new(rect.max.x / 2.0, rect.max.y * 0.75))
+        .show(ctx.get_mut(), |ui| {
+            if ui.button(t!("rematch")).clicked() {
+                cmd.trigger(Rematch);
+            }
+        });
+}
+
+fn on_rematch(
+    _trigger: Trigger<Rematch>,
+    mut server: ResMut<server::ConnectionManager>,
     mut state: ResMut<NextState<GameState>>,
 ) {
-    cmd.start_server();
+    server
+        .send_message_to_target::<DefaultChannel, ReturnToLobbyMessage>(
+            &ReturnToLobbyMessage,
+            NetworkTarget::All,
+        )
+        .unwrap_or_else(|e| {
+            error!("Failed to send return to lobby message: {}", e);
+        });
     state.set(GameState::Lobby);
 }
 
-fn update_server_config(mut server_config: ResMut<ServerConfig>, address: Res<ServerAddress>) {
-    if address.is_changed() {
+/// Present while hosting a game nobody else can join, see [`HostGame::local`].
+#[derive(Resource)]
+struct LocalSession;
+
+/// Socket answering LAN discovery queries while hosting.
+#[derive(Resource, Default)]
+struct DiscoveryHost {
+    socket: Option<UdpSocket>,
+    bound: bool,
+    /// Announced so that browsers list this game once, however they reached it.
+    id: u64,
+}
+
+fn answer_discovery(
+    mut host: ResMut<DiscoveryHost>,
+    players: Res<ConnectedPlayers>,
+    nickname: Res<Nickname>,
+    address: Res<ServerAddress>,
+    state: Res<State<GameState>>,
+    protocol: Res<ProtocolVersion>,
+) {
+    if !host.bound {
+        host.bound = true;
+        host.id = rand::random();
+        host.socket = bind_host_socket();
+        if host.socket.is_none() {
+            warn!("No free port for LAN discovery, the game will not be advertised");
+        }
+    }
+    let Some(socket) = host.socket.as_ref() else {
+        return;
+    };
+    let mut buf = [0u8; 64];
+    while let Ok((len, from)) = socket.recv_from(&mut buf) {
+        let Some(sequence) = std::str::from_utf8(&buf[..len]).ok().and_then(decode_query) else {
+            continue;
+        };
+        let announcement = Announcement {
+            sequence,
+            server: host.id,
+            version: protocol.0,
+            port: address.port,
+            // Bots and the host play too
+            players: players.players.len() + players.bots() + 1,
+            state: format!("{:?}", state.get()),
+            name: nickname.0.clone(),
+        };
+        let _ = socket.send_to(announcement.encode().as_bytes(), from);
+    }
+}
+
+fn on_host_game(
+    trigger: Trigger<HostGame>,
+    mut cmd: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    mut server_config: ResMut<ServerConfig>,
+    address: Res<ServerAddress>,
+    security: Res<SecuritySettings>,
+    network: Res<NetworkSettings>,
+    protocol: Res<ProtocolVersion>,
+) {
+    if trigger.local {
+        // Nothing to listen on, so no client can join
+        cmd.insert_resource(LocalSession);
+        server_config.net = Vec::new();
+    } else {
         let address = SocketAddr::new(
             address
                 .ip
@@ -234,6 +881,153 @@ fn update_server_config(mut server_config: ResMut<ServerConfig>, address: Res<Se
                 .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
             address.port,
         );
-        server_config.net = vec![net_config(address)];
+        let key = if security.require_token {
+            let key = generate_key();
+            match TokenEndpoint::start(address, key, *protocol, security.password.clone()) {
+                Ok(endpoint) => cmd.insert_resource(endpoint),
+                Err(e) => {
+                    error!("Failed to start token endpoint: {}", e);
+                    return;
+                }
+            }
+            key
+        } else {
+            Key::default()
+        };
+        server_config.net = vec![net_config(address, key, network.conditioner())];
+    }
+    cmd.insert_resource(LocalPlayer(0));
+    server_config.replication.send_interval = network.replication_interval();
+    cmd.start_server();
+    state.set(GameState::Lobby);
+}
+
+fn collect_authenticated(endpoint: Res<TokenEndpoint>, mut players: ResMut<ConnectedPlayers>) {
+    players.authenticated.extend(endpoint.drain_issued());
+}
+
+fn close_session(mut cmd: Commands) {
+    cmd.remove_resource::<TokenEndpoint>();
+    cmd.remove_resource::<LocalSession>();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use bevy::state::app::StatesPlugin;
+    use lightyear::prelude::server::{NetworkingState, ServerConfig, ServerPlugins};
+    use lightyear::prelude::{ClientId, Tick};
+    use lightyear::server::events::MessageEvent;
+
+    use crate::auth::SecuritySettings;
+    use crate::game_mode::{GameMode, MatchSettings};
+    use crate::network_debug::NetworkSettings;
+    use crate::rules::GameRules;
+    use crate::server::{
+        ConnectedPlayers, InputGuard, LocalSession, RejectedClients, handle_handshakes,
+        on_host_game, shot_ticks,
+    };
+    use crate::shared::{HandshakeMessage, ProtocolVersion, SharedPlugin};
+    use crate::weapon::WeaponKind;
+    use crate::{GameState, HostGame, ServerAddress};
+
+    /// Server in the lobby that only answers handshakes.
+    fn lobby() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            ServerPlugins::new(ServerConfig::default()),
+            SharedPlugin,
+        ))
+        .init_state::<GameState>()
+        .init_resource::<ConnectedPlayers>()
+        .init_resource::<RejectedClients>()
+        .init_resource::<GameMode>()
+        .init_resource::<MatchSettings>()
+        .init_resource::<GameRules>()
+        .add_systems(Update, handle_handshakes);
+        // Lightyear only creates the connection manager once the plugins are finished
+        app.finish();
+        app.cleanup();
+        app
+    }
+
+    #[test]
+    fn clients_of_other_builds_are_rejected() {
+        let mut app = lobby();
+        let protocol = *app.world().resource::<ProtocolVersion>();
+        let handshake = |version, client| {
+            MessageEvent::new(HandshakeMessage { version }, ClientId::Netcode(client))
+        };
+        app.world_mut().send_event(handshake(protocol.0, 1));
+        app.world_mut().send_event(handshake(protocol.0 ^ 1, 2));
+        app.update();
+
+        let rejected = &app.world().resource::<RejectedClients>().0;
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].0, ClientId::Netcode(2));
+        assert_eq!(app.world().resource::<ConnectedPlayers>().players, vec![1]);
+    }
+
+    #[test]
+    fn local_games_listen_for_no_one() {
+        let mut app = lobby();
+        app.init_resource::<ServerAddress>()
+            .init_resource::<SecuritySettings>()
+            .init_resource::<NetworkSettings>()
+            .add_observer(on_host_game);
+        // Observers are only registered once the world is flushed
+        app.world_mut().flush();
+        app.world_mut().trigger(HostGame { local: true });
+        app.update();
+        app.update();
+
+        assert!(app.world().resource::<ServerConfig>().net.is_empty());
+        assert!(app.world().contains_resource::<LocalSession>());
+        let networking = app.world().resource::<State<NetworkingState>>();
+        assert_eq!(*networking.get(), NetworkingState::Started);
+        let state = app.world().resource::<State<GameState>>();
+        assert_eq!(*state.get(), GameState::Lobby);
+    }
+
+    #[test]
+    fn honest_clients_fire_at_the_cooldown_rate() {
+        let mut guard = InputGuard::default();
+        let cooldown = shot_ticks(WeaponKind::Standard, &GameRules::default());
+        // Long enough for the tick to wrap around
+        for shot in 0..5000u16 {
+            let tick = Tick(shot.wrapping_mul(cooldown as u16));
+            assert!(
+                guard.accept_shot(1, tick, cooldown),
+                "shot {} refused",
+                shot
+            );
+        }
+    }
+
+    #[test]
+    fn shots_inside_the_cooldown_are_refused() {
+        let mut guard = InputGuard::default();
+        let cooldown = shot_ticks(WeaponKind::Standard, &GameRules::default());
+        assert!(guard.accept_shot(1, Tick(100), cooldown));
+        assert!(!guard.accept_shot(1, Tick(100 + cooldown as u16 - 1), cooldown));
+        // Refused shots do not restart the cooldown
+        assert!(guard.accept_shot(1, Tick(100 + cooldown as u16), cooldown));
+        // Other clients keep their own
+        assert!(guard.accept_shot(2, Tick(101), cooldown));
+    }
+
+    #[test]
+    fn slower_weapons_hold_back_the_next_shot() {
+        let mut guard = InputGuard::default();
+        let rules = GameRules::default();
+        let standard = shot_ticks(WeaponKind::Standard, &rules);
+        let laser = shot_ticks(WeaponKind::Laser, &rules);
+        assert!(guard.accept_shot(1, Tick(100), laser));
+        assert!(!guard.accept_shot(1, Tick(100 + standard as u16), standard));
+        assert!(guard.accept_shot(1, Tick(100 + laser as u16), standard));
+        // Back on the standard gun the next shot comes sooner
+        assert!(guard.accept_shot(1, Tick(100 + (laser + standard) as u16), standard));
     }
 }
@@ -4,31 +4,164 @@ use bevy::prelude::*;
 use client::{ComponentSyncMode, LerpFn};
 use lightyear::{prelude::*, utils::bevy::TransformLinearInterpolation};
 
-use serde::{Deserialize, Serialize};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::{
-    Velocity,
+    Lives, Score, Velocity,
     asteroid::LargeAsteroid,
-    player::{PlayerId, ScoreMarker},
+    game_mode::{GameMode, MatchSettings, OnKill, TeamPool},
+    player::{
+        Downed, Eliminated, Owner, PlayerId, PlayerName, PlayerStats, ScoreMarker, ShipInput,
+    },
+    rules::GameRules,
+    schema::shape_of,
+    weapon::{WeaponKind, Weapons},
 };
 
 pub struct SharedPlugin;
 
 pub const SERVER_REPLICATION_INTERVAL: Duration = Duration::from_millis(100);
 pub const FIXED_TIMESTEP_HZ: f64 = 64.0;
+/// Netcode protocol id, the same for every build so that clients with another
+/// [`ProtocolVersion`] still get to the handshake and are told why they are turned away.
+pub const NETCODE_PROTOCOL_ID: u64 = 0x6173_7465_726f_6964;
 
-pub fn shared_config() -> SharedConfig {
+/// Shared lightyear settings. Dedicated servers run in [`Mode::Separate`], every other app
+/// hosts or joins as a client in [`Mode::HostServer`].
+pub fn shared_config(mode: Mode) -> SharedConfig {
     SharedConfig {
         server_replication_send_interval: SERVER_REPLICATION_INTERVAL,
         tick: TickConfig {
             tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
         },
-        mode: Mode::HostServer,
+        mode,
     }
 }
 
+/// Hash of the ship input and every message, channel and component registered by
+/// [`SharedPlugin`], in registration order. Each type is recorded with its wire shape, see
+/// [`shape_of`], so renaming or changing a field changes the protocol. Builds with
+/// different protocols cannot play together.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProtocolVersion(pub u64);
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        // FNV-1a offset basis
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl ProtocolVersion {
+    fn record(&mut self, shape: &str, direction: ChannelDirection) {
+        let direction = match direction {
+            ChannelDirection::ClientToServer => "client_to_server",
+            ChannelDirection::ServerToClient => "server_to_client",
+            ChannelDirection::Bidirectional => "bidirectional",
+        };
+        self.hash(&format!("{}:{};", shape, direction));
+    }
+
+    fn hash(&mut self, entry: &str) {
+        for byte in entry.bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// First message a client sends after connecting.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct StartGameMessage;
+pub struct HandshakeMessage {
+    pub version: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    IncompatibleVersion { server: u64, client: u64 },
+    WrongPassword,
+    TokenUnavailable(String),
+}
+
+impl RejectReason {
+    pub fn describe(&self) -> String {
+        match self {
+            RejectReason::IncompatibleVersion { server, client } => t!(
+                "rejected.version",
+                server = format!("{:016x}", server),
+                client = format!("{:016x}", client)
+            )
+            .to_string(),
+            RejectReason::WrongPassword => t!("rejected.password").to_string(),
+            RejectReason::TokenUnavailable(error) => {
+                t!("rejected.token", error = error).to_string()
+            }
+        }
+    }
+}
+
+/// Reply to an accepted handshake.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WelcomeMessage {
+    /// Value of [`crate::player::PlayerId`] the client's ship will have.
+    pub id: u64,
+    /// The client joined a match in progress and will play from the next one.
+    pub spectating: bool,
+}
+
+/// Sent by the server before it drops a client.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RejectMessage(pub RejectReason);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StartGameMessage {
+    pub mode: GameMode,
+    pub settings: MatchSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KillFeedMessage(pub OnKill);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReturnToLobbyMessage;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerInfoMessage {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerResult {
+    pub id: u64,
+    pub name: String,
+    pub score: u32,
+    pub stats: PlayerStats,
+}
+
+/// Final standings of a match, sent to clients when the game ends.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MatchResults {
+    pub players: Vec<PlayerResult>,
+}
+
+impl MatchResults {
+    pub fn ranked(mut players: Vec<PlayerResult>, mode: GameMode) -> Self {
+        players.sort_by(|a, b| mode.result(b).cmp(&mode.result(a)));
+        Self { players }
+    }
+}
+
+/// Chat line. Clients leave `sender` empty, the server fills it in when relaying.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub sender: String,
+    pub text: String,
+}
+
+/// Numbered probe the server sends on an unreliable channel, gaps show lost packets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProbeMessage(pub u32);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CollisionMessage {
@@ -39,19 +172,135 @@ pub struct CollisionMessage {
 #[derive(Channel)]
 pub struct DefaultChannel;
 
+#[derive(Channel)]
+pub struct ChatChannel;
+
+#[derive(Channel)]
+pub struct ProbeChannel;
+
+/// Wire shape of `T`, or just its name for types [`shape_of`] cannot trace.
+fn shape<T: DeserializeOwned>() -> String {
+    let name = std::any::type_name::<T>();
+    shape_of::<T>().unwrap_or_else(|e| {
+        warn!("Hashing {} into the protocol by name only: {}", name, e);
+        name.to_string()
+    })
+}
+
+fn message<M: Message + Serialize + DeserializeOwned>(
+    app: &mut App,
+    protocol: &mut ProtocolVersion,
+    direction: ChannelDirection,
+) {
+    protocol.record(&shape::<M>(), direction);
+    app.register_message::<M>(direction);
+}
+
+fn component<C: Component + Message + Serialize + DeserializeOwned + PartialEq>(
+    app: &mut App,
+    protocol: &mut ProtocolVersion,
+    direction: ChannelDirection,
+) {
+    protocol.record(&shape::<C>(), direction);
+    app.register_component::<C>(direction);
+}
+
+fn channel<C: Channel>(app: &mut App, protocol: &mut ProtocolVersion, settings: ChannelSettings) {
+    protocol.hash(&format!("{}:{:?};", C::name(), settings.mode));
+    app.add_channel::<C>(settings);
+}
+
+fn input<A: UserAction + DeserializeOwned>(app: &mut App, protocol: &mut ProtocolVersion) {
+    protocol.record(&shape::<A>(), ChannelDirection::ClientToServer);
+    app.add_plugins(InputPlugin::<A>::default());
+}
+
 impl Plugin for SharedPlugin {
     fn build(&self, app: &mut App) {
-        app.register_message::<StartGameMessage>(ChannelDirection::ServerToClient);
-        app.add_channel::<DefaultChannel>(ChannelSettings {
-            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
-            ..default()
-        });
-        app.register_component::<Transform>(ChannelDirection::ServerToClient)
-            .add_interpolation(ComponentSyncMode::Full)
-            .add_interpolation_fn(TransformLinearInterpolation::lerp);
-        app.register_component::<Velocity>(ChannelDirection::ServerToClient);
-        app.register_component::<LargeAsteroid>(ChannelDirection::ServerToClient);
-        app.register_component::<PlayerId>(ChannelDirection::ServerToClient);
-        app.register_component::<ScoreMarker>(ChannelDirection::ServerToClient);
+        use ChannelDirection::{Bidirectional, ClientToServer, ServerToClient};
+
+        let mut protocol = ProtocolVersion::default();
+        // The handshake must stay registered first so that its network ids
+        // match between builds with otherwise different protocols.
+        message::<HandshakeMessage>(app, &mut protocol, ClientToServer);
+        message::<RejectMessage>(app, &mut protocol, ServerToClient);
+        input::<ShipInput>(app, &mut protocol);
+        message::<WelcomeMessage>(app, &mut protocol, ServerToClient);
+        message::<StartGameMessage>(app, &mut protocol, ServerToClient);
+        message::<ReturnToLobbyMessage>(app, &mut protocol, ServerToClient);
+        message::<KillFeedMessage>(app, &mut protocol, ServerToClient);
+        message::<MatchResults>(app, &mut protocol, ServerToClient);
+        message::<GameRules>(app, &mut protocol, ServerToClient);
+        message::<PlayerInfoMessage>(app, &mut protocol, ClientToServer);
+        message::<ChatMessage>(app, &mut protocol, Bidirectional);
+        message::<ProbeMessage>(app, &mut protocol, ServerToClient);
+        channel::<DefaultChannel>(
+            app,
+            &mut protocol,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            },
+        );
+        channel::<ChatChannel>(
+            app,
+            &mut protocol,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            },
+        );
+        channel::<ProbeChannel>(
+            app,
+            &mut protocol,
+            ChannelSettings {
+                mode: ChannelMode::UnorderedUnreliable,
+                ..default()
+            },
+        );
+        component::<Transform>(app, &mut protocol, ServerToClient);
+        app.add_interpolation::<Transform>(ComponentSyncMode::Full);
+        app.add_interpolation_fn::<Transform>(TransformLinearInterpolation::lerp);
+        component::<Velocity>(app, &mut protocol, ServerToClient);
+        component::<LargeAsteroid>(app, &mut protocol, ServerToClient);
+        component::<PlayerId>(app, &mut protocol, ServerToClient);
+        component::<ScoreMarker>(app, &mut protocol, ServerToClient);
+        component::<Owner>(app, &mut protocol, ServerToClient);
+        component::<PlayerName>(app, &mut protocol, ServerToClient);
+        component::<Score>(app, &mut protocol, ServerToClient);
+        component::<Lives>(app, &mut protocol, ServerToClient);
+        component::<PlayerStats>(app, &mut protocol, ServerToClient);
+        component::<Eliminated>(app, &mut protocol, ServerToClient);
+        component::<Downed>(app, &mut protocol, ServerToClient);
+        component::<TeamPool>(app, &mut protocol, ServerToClient);
+        component::<WeaponKind>(app, &mut protocol, ServerToClient);
+        component::<Weapons>(app, &mut protocol, ServerToClient);
+        app.insert_resource(protocol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use bevy::state::app::StatesPlugin;
+    use lightyear::prelude::server::{ServerConfig, ServerPlugins};
+
+    use crate::shared::{ProtocolVersion, SharedPlugin};
+
+    fn protocol() -> ProtocolVersion {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            ServerPlugins::new(ServerConfig::default()),
+            SharedPlugin,
+        ));
+        *app.world().resource::<ProtocolVersion>()
+    }
+
+    #[test]
+    fn builds_agree_on_the_protocol() {
+        assert_eq!(protocol(), protocol());
+        assert_ne!(protocol(), ProtocolVersion::default());
     }
 }
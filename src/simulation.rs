@@ -0,0 +1,1118 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rand::plugin::EntropyPlugin;
+use bevy_spatial::{AutomaticUpdate, SpatialSet, SpatialStructure, TransformMode};
+use lightyear::prelude::is_server;
+
+use crate::asteroid::{
+    bounce_asteroids, divide_on_collision, handle_grace, ramp_spawn_rate, spawn_asteroid,
+    spawn_asteroid_spawner,
+};
+use crate::game_mode::{GameMode, resolve_ship_hits};
+use crate::player::{
+    Downed, Eliminated, Owner, PlayerId, PlayerStats, ScoreMarker, ShipInput, add_score,
+    clear_player_grace, player_grace, resolve_bullet_collisions, resolve_player_collisions,
+};
+use crate::rules::{GameRules, ProjectileRange};
+use crate::shared::FIXED_TIMESTEP_HZ;
+use crate::weapon::{
+    LASER_RANGE, WeaponKind, Weapons, beam, laser_target, mine, missile, steer_missiles,
+};
+use crate::{
+    AngularVelocity, CircleCollider, CleanupOnGameOver, ColliderReach, CollisionLayers, GameState,
+    ProjectileHitAsteroid, ProjectileHitShip, RngType, ShipHitAsteroid, SpatialMarker, Swept,
+    VELOCITY_SCALE, Velocity, apply_velocity, check_collisions, check_for_gameover, cleanup,
+    update_collider_reach, update_sweeps, wrap_around,
+};
+
+/// Movement, collisions, asteroids, damage, scoring and game over. Nothing here renders or
+/// touches the network, so the simulation also runs under `MinimalPlugins`.
+pub struct SimulationPlugin;
+
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SimulationSet {
+    /// Screen wrap and collision detection, after movement on the same fixed tick.
+    Physics,
+    /// Reactions to the collisions found by [`SimulationSet::Physics`].
+    Resolve,
+}
+
+/// The simulation runs on its own, without a server deciding the outcomes, e.g. in tests.
+#[derive(Resource)]
+pub struct Headless;
+
+/// Systems deciding outcomes run on the server, or anywhere without one.
+pub fn authority() -> impl Condition<()> {
+    is_server.or(resource_exists::<Headless>)
+}
+
+/// Input of one ship for the current tick, whether from a client, a bot or a test.
+#[derive(Event, Clone, Debug)]
+pub struct ShipAction {
+    pub player: u64,
+    pub input: ShipInput,
+}
+
+/// Entities already used up by a hit this tick, so that nothing scores, splits or hurts twice.
+#[derive(Resource, Default)]
+pub struct ResolvedHits(HashSet<Entity>);
+
+impl ResolvedHits {
+    /// Claims `entities` for one hit, unless any of them already took part in another.
+    pub fn claim(&mut self, entities: &[Entity]) -> bool {
+        if entities.iter().any(|it| self.0.contains(it)) {
+            return false;
+        }
+        self.0.extend(entities);
+        true
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+}
+
+fn clear_resolved_hits(mut hits: ResMut<ResolvedHits>) {
+    hits.0.clear();
+}
+
+/// Triggered on a ship to fire a projectile.
+#[derive(Event)]
+pub struct Fire;
+
+/// Time until a ship may fire again, kept by the authority for every player.
+#[derive(Component)]
+pub struct ShotCooldown(Timer);
+
+impl ShotCooldown {
+    /// Cooldown of a ship that may fire right away.
+    pub fn new(rules: &GameRules) -> Self {
+        let mut timer = Timer::from_seconds(rules.shoot_timeout, TimerMode::Once);
+        timer.tick(timer.duration());
+        Self(timer)
+    }
+
+    pub fn ready(&self) -> bool {
+        self.0.finished()
+    }
+}
+
+pub fn tick_shot_cooldowns(mut cooldowns: Query<&mut ShotCooldown>, time: Res<Time>) {
+    for mut cooldown in &mut cooldowns {
+        cooldown.0.tick(time.delta());
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            EntropyPlugin::<RngType>::default(),
+            // Rebuilt every tick from where things are now, so that collisions only depend
+            // on the seed and the inputs and not on the frame rate
+            AutomaticUpdate::<SpatialMarker>::new()
+                .with_schedule(FixedUpdate)
+                .with_frequency(Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ))
+                .with_spatial_ds(SpatialStructure::KDTree2)
+                .with_transform(TransformMode::Transform),
+        ))
+        .init_state::<GameState>()
+        .init_resource::<GameRules>()
+        .init_resource::<ResolvedHits>()
+        .init_resource::<ColliderReach>()
+        .add_event::<ShipHitAsteroid>()
+        .add_event::<ProjectileHitShip>()
+        .add_event::<ProjectileHitAsteroid>()
+        .add_event::<ShipAction>()
+        .configure_sets(
+            FixedUpdate,
+            (
+                (SimulationSet::Physics, SimulationSet::Resolve)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+                SpatialSet.after(wrap_around).before(check_collisions),
+            ),
+        )
+        .add_systems(Startup, spawn_asteroid_spawner)
+        .add_systems(
+            FixedUpdate,
+            (
+                (tick_shot_cooldowns, apply_ship_actions, steer_missiles)
+                    .chain()
+                    .run_if(authority()),
+                apply_ship_dynamics,
+                apply_velocity,
+                expire_projectiles,
+                // Spawned on the fixed tick so that seeded fields play out the same every run
+                (ramp_spawn_rate, spawn_asteroid)
+                    .chain()
+                    .run_if(authority()),
+            )
+                .chain()
+                .before(SimulationSet::Physics)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                (
+                    wrap_around,
+                    update_collider_reach,
+                    check_collisions,
+                    update_sweeps,
+                )
+                    .chain()
+                    .in_set(SimulationSet::Physics),
+                (
+                    handle_grace,
+                    clear_player_grace,
+                    (
+                        clear_resolved_hits,
+                        resolve_bullet_collisions,
+                        resolve_ship_hits.run_if(resource_equals(GameMode::Versus)),
+                        resolve_player_collisions,
+                        bounce_asteroids,
+                        check_for_gameover,
+                    )
+                        .chain()
+                        .run_if(authority()),
+                )
+                    .in_set(SimulationSet::Resolve),
+            ),
+        )
+        .add_systems(OnEnter(GameState::GameOver), cleanup::<CleanupOnGameOver>)
+        .add_observer(divide_on_collision)
+        .add_observer(fire_projectile)
+        .add_observer(player_grace)
+        .add_observer(add_score);
+    }
+}
+
+/// Gameplay components of a projectile fired from `transform` by a ship moving at `velocity`.
+pub fn projectile(
+    transform: &Transform,
+    velocity: &Velocity,
+    owner: PlayerId,
+    rules: &GameRules,
+) -> impl Bundle {
+    let direction = transform.rotation * Vec3::Y;
+    (
+        Transform::from_translation(transform.translation),
+        Velocity {
+            x: velocity.x + direction.x * rules.projectile_speed,
+            y: velocity.y + direction.y * rules.projectile_speed,
+        },
+        match rules.projectile_range {
+            ProjectileRange::Seconds(secs) => {
+                Lifetime::Time(Timer::from_seconds(secs, TimerMode::Once))
+            }
+            ProjectileRange::Distance(distance) => Lifetime::Distance(distance),
+        },
+        CircleCollider::projectile(rules.projectile_radius),
+        Swept(transform.translation.xy()),
+        ScoreMarker,
+        Owner(owner),
+        WeaponKind::Standard,
+        CleanupOnGameOver,
+    )
+}
+
+/// Remaining life of a projectile.
+#[derive(Component)]
+pub enum Lifetime {
+    Time(Timer),
+    /// Pixels left to travel.
+    Distance(f32),
+}
+
+fn expire_projectiles(
+    mut cmd: Commands,
+    mut projectiles: Query<(Entity, &mut Lifetime, &Velocity)>,
+    time: Res<Time>,
+) {
+    for (e, mut lifetime, velocity) in &mut projectiles {
+        let expired = match &mut *lifetime {
+            Lifetime::Time(timer) => timer.tick(time.delta()).finished(),
+            Lifetime::Distance(left) => {
+                *left -=
+                    Vec2::new(velocity.x, velocity.y).length() * VELOCITY_SCALE * time.delta_secs();
+                *left <= 0.0
+            }
+        };
+        if expired {
+            cmd.entity(e).despawn();
+        }
+    }
+}
+
+/// Applies the actions of every client, bot or test to their ships.
+pub fn apply_ship_actions(
+    mut actions: EventReader<ShipAction>,
+    mut players: Query<
+        (
+            &PlayerId,
+            Entity,
+            &Transform,
+            &mut Velocity,
+            &mut AngularVelocity,
+            &mut Weapons,
+        ),
+        (Without<Eliminated>, Without<Downed>),
+    >,
+    mut cmd: Commands,
+    time: Res<Time>,
+    rules: Res<GameRules>,
+) {
+    for ShipAction { player, input } in actions.read() {
+        let ship = players.iter_mut().find(|it| it.0.0 == *player);
+        if let Some((_, e, transform, mut velocity, mut spin, mut weapons)) = ship {
+            steer_ship(
+                input,
+                transform,
+                &mut velocity,
+                &mut spin,
+                &rules,
+                time.delta_secs(),
+            );
+            weapons.hold_switch(input.switch_weapon);
+            if input.shoot {
+                cmd.trigger_targets(Fire, e);
+            }
+        }
+    }
+}
+
+/// Thrust and turn of a ship for one tick of `input`, also used to replay recorded input.
+pub fn steer_ship(
+    input: &ShipInput,
+    transform: &Transform,
+    velocity: &mut Velocity,
+    spin: &mut AngularVelocity,
+    rules: &GameRules,
+    delta: f32,
+) {
+    let handling = rules.physics.handling();
+    let direction = transform.rotation * Vec3::Y;
+    let translation = direction * rules.acceleration * delta;
+    match input.thrust.signum() {
+        1 => velocity.update(translation.xy()),
+        -1 => velocity.update(-translation.xy() * handling.reverse_thrust),
+        _ => (),
+    }
+    if input.rotate != 0 {
+        spin.steer(
+            -1.0 * input.rotate.clamp(-1, 1) as f32 * rules.rotation_speed,
+            handling.rotational_inertia,
+            delta,
+        );
+    }
+    velocity.max(rules.max_velocity);
+}
+
+/// Turns ships and slows them down according to the physics profile of the rules.
+pub fn apply_ship_dynamics(
+    mut ships: Query<(&mut Transform, &mut Velocity, &mut AngularVelocity)>,
+    time: Res<Time>,
+    rules: Res<GameRules>,
+) {
+    let handling = rules.physics.handling();
+    let delta = time.delta_secs();
+    for (mut transform, mut velocity, mut spin) in &mut ships {
+        transform.rotate_z(spin.0 * delta);
+        // Infinite angular drag stops the turn once there is no input for it
+        spin.0 *= (-handling.angular_drag * delta).exp();
+        let keep = (-handling.drag * delta).exp();
+        velocity.x *= keep;
+        velocity.y *= keep;
+    }
+}
+
+/// Fires the selected weapon of a ship. Lasers hit right away, the rest spawn a projectile.
+fn fire_projectile(
+    trigger: Trigger<Fire>,
+    mut players: Query<(
+        &Transform,
+        &Velocity,
+        &PlayerId,
+        &mut PlayerStats,
+        &mut ShotCooldown,
+        &mut Weapons,
+    )>,
+    projectiles: Query<(&Owner, &WeaponKind), With<ScoreMarker>>,
+    targets: Query<(Entity, &Transform, &CircleCollider, Option<&PlayerId>)>,
+    mut asteroid_hits: EventWriter<ProjectileHitAsteroid>,
+    mut ship_hits: EventWriter<ProjectileHitShip>,
+    mut cmd: Commands,
+    rules: Res<GameRules>,
+    mode: Res<GameMode>,
+) {
+    let Ok((transform, velocity, player, mut stats, mut cooldown, mut weapons)) =
+        players.get_mut(trigger.entity())
+    else {
+        return;
+    };
+    if !cooldown.ready() {
+        return;
+    }
+    let kind = weapons.current().kind;
+    // Only the standard gun is capped, other weapons are limited by their ammo
+    let in_flight = projectiles
+        .iter()
+        .filter(|(owner, shot)| owner.0 == *player && **shot == WeaponKind::Standard)
+        .count();
+    if kind == WeaponKind::Standard && in_flight >= rules.max_projectiles as usize {
+        return;
+    }
+    cooldown.0 = Timer::from_seconds(kind.cooldown(&rules), TimerMode::Once);
+    if kind != WeaponKind::Laser {
+        let shot = cmd
+            .spawn(projectile(transform, velocity, *player, &rules))
+            .id();
+        weapons.spend();
+        stats.shots_fired += 1;
+        match kind {
+            WeaponKind::Mine => {
+                cmd.entity(shot).insert(mine(velocity, &rules));
+            }
+            WeaponKind::Missile => {
+                cmd.entity(shot)
+                    .insert(missile(transform, velocity, &rules));
+            }
+            _ => (),
+        }
+        return;
+    }
+    let direction = (transform.rotation * Vec3::Y).xy();
+    // Teammates are only in the line of fire in versus
+    let hit = laser_target(
+        transform.translation.xy(),
+        direction,
+        targets
+            .iter()
+            .filter(|(_, _, collider, id)| {
+                *id != Some(player)
+                    && (*mode == GameMode::Versus || collider.layer != CollisionLayers::SHIP)
+            })
+            .map(|(e, at, collider, _)| (e, at.translation.xy(), collider)),
+    );
+    let length = hit.map_or(LASER_RANGE, |(.., distance)| distance);
+    let shot = cmd.spawn(beam(transform, length, *player)).id();
+    weapons.spend();
+    stats.shots_fired += 1;
+    match hit {
+        Some((asteroid, CollisionLayers::ASTEROID, _)) => {
+            asteroid_hits.send(ProjectileHitAsteroid {
+                projectile: shot,
+                asteroid,
+            });
+        }
+        Some((ship, CollisionLayers::SHIP, _)) => {
+            ship_hits.send(ProjectileHitShip {
+                projectile: shot,
+                ship,
+            });
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::prelude::*;
+    use bevy::state::app::StatesPlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy_rand::prelude::Entropy;
+    use bevy_rand::traits::ForkableRng;
+    use rand::SeedableRng;
+
+    use crate::asteroid::{AsteroidSpawner, LargeAsteroid, asteroid};
+    use crate::game_mode::{GameMode, MatchClock, MatchSettings, TeamPool};
+    use crate::player::{
+        Downed, Eliminated, OnPlayerDamage, PlayerAction, PlayerGrace, PlayerId, PlayerStats,
+        ScoreMarker, ShipInput, player_state,
+    };
+    use crate::rules::{AsteroidPhysics, GameRules, PhysicsProfile, ProjectileRange};
+    use crate::shared::{FIXED_TIMESTEP_HZ, MatchResults};
+    use crate::simulation::{ShipAction, projectile};
+    use crate::weapon::{WeaponKind, Weapons};
+    use crate::{
+        CircleCollider, GamePlugin, GameState, Lives, RngType, Score, ShipHitAsteroid, Velocity,
+        WINDOW_HEIGHT, WINDOW_WIDTH, WrapTimeout,
+    };
+
+    /// Headless app stepped one fixed tick per update.
+    struct Harness {
+        app: App,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let mut app = App::new();
+            app.add_plugins((
+                MinimalPlugins,
+                TransformPlugin,
+                StatesPlugin,
+                GamePlugin::headless(),
+            ))
+            .insert_resource(Time::<Fixed>::from_hz(FIXED_TIMESTEP_HZ))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / FIXED_TIMESTEP_HZ,
+            )));
+            app.world_mut()
+                .resource_mut::<NextState<GameState>>()
+                .set(GameState::Playing);
+            app.update();
+            Self { app }
+        }
+
+        fn spawn_ship(&mut self, id: u64, position: Vec2) -> Entity {
+            let loadout = self.app.world().resource::<MatchSettings>().loadout.clone();
+            self.app
+                .world_mut()
+                .spawn((
+                    player_state(
+                        id,
+                        format!("Player {}", id),
+                        &GameRules::default(),
+                        &loadout,
+                    ),
+                    Transform::from_translation(position.extend(0.0)),
+                    Velocity { x: 0.0, y: 0.0 },
+                ))
+                .id()
+        }
+
+        fn spawn_large_asteroid(&mut self, position: Vec2) -> Entity {
+            let rules = GameRules::default();
+            self.app
+                .world_mut()
+                .spawn((
+                    asteroid(position.extend(0.0), Velocity { x: 0.0, y: 0.0 }, &rules),
+                    LargeAsteroid,
+                    CircleCollider::asteroid(rules.large_asteroid_radius),
+                ))
+                .id()
+        }
+
+        fn spawn_small_asteroid(&mut self, position: Vec2) -> Entity {
+            let rules = GameRules::default();
+            self.app
+                .world_mut()
+                .spawn((
+                    asteroid(position.extend(0.0), Velocity { x: 0.0, y: 0.0 }, &rules),
+                    CircleCollider::asteroid(rules.small_asteroid_radius),
+                ))
+                .id()
+        }
+
+        /// Projectile of `owner` standing still at `position`.
+        fn spawn_projectile(&mut self, owner: u64, position: Vec2) -> Entity {
+            let rules = GameRules::default();
+            let still = Velocity {
+                x: 0.0,
+                y: -rules.projectile_speed,
+            };
+            self.app
+                .world_mut()
+                .spawn(projectile(
+                    &Transform::from_translation(position.extend(0.0)),
+                    &still,
+                    PlayerId(owner),
+                    &rules,
+                ))
+                .id()
+        }
+
+        /// Plays the asteroid field of `seed` from now on.
+        fn seed(&mut self, seed: u64) {
+            let mut spawner = self
+                .app
+                .world_mut()
+                .query::<(&mut AsteroidSpawner, &mut Entropy<RngType>)>();
+            let (mut spawner, mut rng) = spawner.single_mut(self.app.world_mut());
+            *rng = Entropy::seed_from_u64(seed);
+            spawner.splits = rng.fork_rng();
+        }
+
+        /// Applies `action` to the ship of `player` on the next tick.
+        fn act(&mut self, player: u64, action: PlayerAction) {
+            self.input(player, action.into());
+        }
+
+        fn input(&mut self, player: u64, input: ShipInput) {
+            self.app
+                .world_mut()
+                .send_event(ShipAction { player, input });
+        }
+
+        fn step(&mut self, ticks: usize) {
+            for _ in 0..ticks {
+                self.app.update();
+            }
+        }
+
+        fn asteroids(&mut self) -> Vec<bool> {
+            self.app
+                .world_mut()
+                .query_filtered::<Has<LargeAsteroid>, (With<WrapTimeout>, Without<ScoreMarker>)>()
+                .iter(self.app.world())
+                .collect()
+        }
+
+        fn projectiles(&mut self) -> usize {
+            self.app
+                .world_mut()
+                .query_filtered::<(), With<ScoreMarker>>()
+                .iter(self.app.world())
+                .count()
+        }
+
+        fn state(&self) -> GameState {
+            self.app
+                .world()
+                .resource::<State<GameState>>()
+                .get()
+                .clone()
+        }
+    }
+
+    #[test]
+    fn shooting_large_asteroid_splits_it_and_scores() {
+        let mut sim = Harness::new();
+        // Ships face up by default
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.spawn_large_asteroid(Vec2::new(500.0, 700.0));
+        sim.step(2);
+
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(30);
+
+        assert_eq!(sim.asteroids(), vec![false, false]);
+        assert_eq!(sim.app.world().get::<Score>(ship), Some(&Score(25)));
+    }
+
+    #[test]
+    fn fast_projectiles_do_not_tunnel_through_asteroids() {
+        let mut sim = Harness::new();
+        let rules = GameRules::default();
+        sim.spawn_small_asteroid(Vec2::new(500.0, 480.0));
+        sim.step(2);
+
+        // Crosses the asteroid within a single tick
+        sim.app.world_mut().spawn(projectile(
+            &Transform::from_xyz(500.0, 400.0, 0.0),
+            &Velocity { x: 0.0, y: 86.0 },
+            PlayerId(1),
+            &rules,
+        ));
+        sim.step(1);
+
+        assert_eq!(sim.asteroids(), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn asteroids_do_not_score_in_versus() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameMode::Versus);
+        let ship = sim.spawn_ship(1, Vec2::new(100.0, 100.0));
+        sim.spawn_small_asteroid(Vec2::new(500.0, 600.0));
+        sim.step(2);
+
+        sim.spawn_projectile(1, Vec2::new(500.0, 600.0));
+        sim.step(3);
+
+        assert!(sim.asteroids().is_empty());
+        assert_eq!(sim.app.world().get::<Score>(ship), Some(&Score(0)));
+        let stats = sim.app.world().get::<PlayerStats>(ship).unwrap();
+        assert_eq!(stats.asteroids_destroyed, 1);
+    }
+
+    #[test]
+    fn one_projectile_breaks_one_asteroid() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(100.0, 100.0));
+        sim.spawn_small_asteroid(Vec2::new(500.0, 600.0));
+        sim.spawn_small_asteroid(Vec2::new(505.0, 600.0));
+        sim.step(2);
+
+        sim.spawn_projectile(1, Vec2::new(502.0, 600.0));
+        sim.step(3);
+
+        assert_eq!(sim.asteroids(), vec![false]);
+        assert_eq!(sim.app.world().get::<Score>(ship), Some(&Score(10)));
+        let stats = sim.app.world().get::<PlayerStats>(ship).unwrap();
+        assert_eq!(stats.shots_hit, 1);
+    }
+
+    #[test]
+    fn asteroid_hit_by_two_projectiles_splits_and_scores_once() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(100.0, 100.0));
+        sim.spawn_large_asteroid(Vec2::new(500.0, 600.0));
+        sim.step(2);
+
+        sim.spawn_projectile(1, Vec2::new(500.0, 600.0));
+        sim.spawn_projectile(1, Vec2::new(505.0, 600.0));
+        sim.step(3);
+
+        assert_eq!(sim.asteroids(), vec![false, false]);
+        assert_eq!(sim.app.world().get::<Score>(ship), Some(&Score(25)));
+        assert_eq!(sim.projectiles(), 1);
+    }
+
+    #[test]
+    fn overlapping_colliders_are_reported_once() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        let asteroid = sim.spawn_large_asteroid(Vec2::new(510.0, 500.0));
+        let mut cursor = sim
+            .app
+            .world()
+            .resource::<Events<ShipHitAsteroid>>()
+            .get_cursor();
+        let mut hits = Vec::new();
+        for _ in 0..5 {
+            sim.step(1);
+            let events = sim.app.world().resource::<Events<ShipHitAsteroid>>();
+            hits.extend(cursor.read(events).copied());
+        }
+
+        assert_eq!(hits, vec![ShipHitAsteroid { ship, asteroid }]);
+    }
+
+    #[test]
+    fn projectiles_fly_as_far_as_their_range() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameRules {
+            projectile_range: ProjectileRange::Distance(200.0),
+            ..default()
+        });
+        sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.act(1, PlayerAction::Shoot);
+        // 10 units of velocity cover about 15 pixels per tick
+        sim.step(10);
+        assert_eq!(sim.projectiles(), 1);
+        sim.step(5);
+        assert_eq!(sim.projectiles(), 0);
+    }
+
+    #[test]
+    fn players_have_a_limited_number_of_projectiles() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameRules {
+            max_projectiles: 2,
+            shoot_timeout: 0.0,
+            ..default()
+        });
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        for _ in 0..4 {
+            sim.act(1, PlayerAction::Shoot);
+            sim.step(1);
+        }
+
+        assert_eq!(sim.projectiles(), 2);
+        let stats = sim.app.world().get::<PlayerStats>(ship).unwrap();
+        assert_eq!(stats.shots_fired, 2);
+    }
+
+    #[test]
+    fn ships_fire_no_faster_than_the_cooldown() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        for _ in 0..4 {
+            sim.act(1, PlayerAction::Shoot);
+            sim.step(1);
+        }
+        assert_eq!(sim.projectiles(), 1);
+
+        sim.step(FIXED_TIMESTEP_HZ as usize / 2);
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(1);
+        let stats = sim.app.world().get::<PlayerStats>(ship).unwrap();
+        assert_eq!(stats.shots_fired, 2);
+    }
+
+    #[test]
+    fn oversized_turns_are_clamped() {
+        let mut sim = Harness::new();
+        let honest = sim.spawn_ship(1, Vec2::new(300.0, 500.0));
+        let cheater = sim.spawn_ship(2, Vec2::new(700.0, 500.0));
+        sim.act(1, PlayerAction::Rotate(1));
+        sim.act(2, PlayerAction::Rotate(i8::MAX));
+        sim.step(1);
+
+        let angle = |ship| {
+            let transform = sim.app.world().get::<Transform>(ship).unwrap();
+            transform.rotation.to_euler(EulerRot::XYZ).2
+        };
+        assert_eq!(angle(cheater), angle(honest));
+    }
+
+    #[test]
+    fn ships_thrust_turn_and_shoot_on_the_same_tick() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.input(
+            1,
+            ShipInput {
+                thrust: 1,
+                rotate: 1,
+                shoot: true,
+                ..default()
+            },
+        );
+        sim.step(1);
+
+        let velocity = sim.app.world().get::<Velocity>(ship).unwrap();
+        assert!(velocity.y > 0.0);
+        let transform = sim.app.world().get::<Transform>(ship).unwrap();
+        assert!(transform.rotation.to_euler(EulerRot::XYZ).2 < 0.0);
+        assert_eq!(sim.projectiles(), 1);
+    }
+
+    fn armed(loadout: Vec<WeaponKind>) -> Harness {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(MatchSettings {
+            loadout,
+            ..default()
+        });
+        sim
+    }
+
+    #[test]
+    fn lasers_hit_the_first_asteroid_in_line() {
+        let mut sim = armed(vec![WeaponKind::Laser]);
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 300.0));
+        sim.spawn_large_asteroid(Vec2::new(500.0, 500.0));
+        sim.spawn_large_asteroid(Vec2::new(500.0, 800.0));
+        sim.act(1, PlayerAction::SwitchWeapon);
+        sim.step(1);
+
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(2);
+
+        let mut asteroids = sim.asteroids();
+        asteroids.sort();
+        assert_eq!(asteroids, vec![false, false, true]);
+        assert_eq!(sim.app.world().get::<Score>(ship), Some(&Score(25)));
+    }
+
+    #[test]
+    fn lasers_pass_teammates_outside_versus() {
+        let mut sim = armed(vec![WeaponKind::Laser]);
+        sim.spawn_ship(1, Vec2::new(500.0, 300.0));
+        let teammate = sim.spawn_ship(2, Vec2::new(500.0, 400.0));
+        sim.spawn_large_asteroid(Vec2::new(500.0, 600.0));
+        sim.act(1, PlayerAction::SwitchWeapon);
+        sim.step(1);
+
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(2);
+
+        assert_eq!(sim.asteroids(), vec![false, false]);
+        assert_eq!(sim.app.world().get::<Lives>(teammate), Some(&Lives(3)));
+    }
+
+    #[test]
+    fn missiles_home_in_on_asteroids() {
+        let mut sim = armed(vec![WeaponKind::Missile]);
+        sim.spawn_ship(1, Vec2::new(500.0, 300.0));
+        // A straight shot would pass the asteroid by
+        sim.spawn_small_asteroid(Vec2::new(540.0, 500.0));
+        sim.act(1, PlayerAction::SwitchWeapon);
+        sim.step(1);
+
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(40);
+
+        assert!(sim.asteroids().is_empty());
+    }
+
+    #[test]
+    fn empty_weapons_fall_back_to_the_standard_gun() {
+        let mut sim = armed(vec![WeaponKind::Mine]);
+        // A field where no asteroid drifts into the mines
+        sim.seed(7);
+        let cooldown = (GameRules::default().shoot_timeout * FIXED_TIMESTEP_HZ as f32) as usize + 1;
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.act(1, PlayerAction::SwitchWeapon);
+        sim.step(1);
+        let ammo = WeaponKind::Mine.ammo().unwrap();
+        for _ in 0..ammo {
+            sim.act(1, PlayerAction::Shoot);
+            sim.step(cooldown);
+        }
+        // More mines are out than the cap allows, the standard gun still fires
+        sim.act(1, PlayerAction::Shoot);
+        sim.step(1);
+
+        let weapons = sim.app.world().get::<Weapons>(ship).unwrap();
+        assert_eq!(weapons.current().kind, WeaponKind::Standard);
+        let mines = sim
+            .app
+            .world_mut()
+            .query::<&WeaponKind>()
+            .iter(sim.app.world())
+            .filter(|it| **it == WeaponKind::Mine)
+            .count();
+        assert_eq!(mines, ammo as usize);
+        assert_eq!(sim.projectiles(), ammo as usize + 1);
+    }
+
+    #[test]
+    fn ships_thrust_and_turn() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        for _ in 0..10 {
+            sim.act(1, PlayerAction::Forward);
+            sim.step(1);
+        }
+        let transform = sim.app.world().get::<Transform>(ship).unwrap();
+        assert!(transform.translation.y > 500.0);
+        assert_eq!(transform.translation.x, 500.0);
+
+        sim.act(1, PlayerAction::Rotate(1));
+        sim.step(1);
+        let transform = sim.app.world().get::<Transform>(ship).unwrap();
+        assert!(transform.rotation.to_euler(EulerRot::XYZ).2 < 0.0);
+    }
+
+    #[test]
+    fn only_arcade_ships_slow_down_on_their_own() {
+        for (physics, slows_down) in [
+            (PhysicsProfile::Arcade, true),
+            (PhysicsProfile::Classic, false),
+            (PhysicsProfile::Newtonian, false),
+        ] {
+            let mut sim = Harness::new();
+            sim.app.insert_resource(GameRules {
+                physics,
+                ..default()
+            });
+            let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+            sim.app
+                .world_mut()
+                .entity_mut(ship)
+                .insert(Velocity { x: 1.0, y: 0.0 });
+            sim.step(10);
+            let velocity = sim.app.world().get::<Velocity>(ship).unwrap();
+            assert_eq!(velocity.x < 1.0, slows_down, "{:?}", physics);
+        }
+    }
+
+    #[test]
+    fn reverse_thrust_brakes() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.app
+            .world_mut()
+            .entity_mut(ship)
+            .insert(Velocity { x: 0.0, y: 2.0 });
+        for _ in 0..10 {
+            sim.act(1, PlayerAction::Reverse);
+            sim.step(1);
+        }
+        let velocity = sim.app.world().get::<Velocity>(ship).unwrap();
+        assert!(velocity.y < 2.0);
+    }
+
+    #[test]
+    fn newtonian_ships_keep_turning() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameRules {
+            physics: PhysicsProfile::Newtonian,
+            ..default()
+        });
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        for _ in 0..5 {
+            sim.act(1, PlayerAction::Rotate(1));
+            sim.step(1);
+        }
+        let angle = |sim: &Harness| {
+            let transform = sim.app.world().get::<Transform>(ship).unwrap();
+            transform.rotation.to_euler(EulerRot::XYZ).2
+        };
+        let turned = angle(&sim);
+        sim.step(5);
+        assert!(angle(&sim) < turned);
+    }
+
+    #[test]
+    fn elastic_asteroids_bounce_off_each_other() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameRules {
+            asteroid_physics: AsteroidPhysics::Elastic,
+            ..default()
+        });
+        let left = sim.spawn_large_asteroid(Vec2::new(500.0, 500.0));
+        let right = sim.spawn_large_asteroid(Vec2::new(600.0, 500.0));
+        let world = sim.app.world_mut();
+        world.entity_mut(left).insert(Velocity { x: 1.0, y: 0.0 });
+        world.entity_mut(right).insert(Velocity { x: -1.0, y: 0.0 });
+        sim.step(20);
+
+        assert_eq!(sim.asteroids(), vec![true, true]);
+        // Equal masses swap their velocities
+        assert!(sim.app.world().get::<Velocity>(left).unwrap().x < 0.0);
+        assert!(sim.app.world().get::<Velocity>(right).unwrap().x > 0.0);
+    }
+
+    #[test]
+    fn ships_bounce_off_elastic_asteroids_unless_ramming() {
+        for (speed, breaks) in [(1.0, false), (3.0, true)] {
+            let mut sim = Harness::new();
+            sim.app.insert_resource(GameRules {
+                asteroid_physics: AsteroidPhysics::Elastic,
+                ..default()
+            });
+            let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+            sim.spawn_large_asteroid(Vec2::new(500.0, 600.0));
+            sim.app
+                .world_mut()
+                .entity_mut(ship)
+                .insert(Velocity { x: 0.0, y: speed });
+            sim.step(40);
+
+            if breaks {
+                assert_eq!(sim.asteroids(), vec![false, false]);
+            } else {
+                assert_eq!(sim.asteroids(), vec![true]);
+                assert!(sim.app.world().get::<Velocity>(ship).unwrap().y < 0.0);
+                assert_eq!(sim.app.world().get::<Lives>(ship), Some(&Lives(3)));
+            }
+        }
+    }
+
+    #[test]
+    fn ships_wrap_around_the_screen() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(1915.0, 500.0));
+        sim.app
+            .world_mut()
+            .entity_mut(ship)
+            .insert(Velocity { x: 3.0, y: 0.0 });
+        sim.step(2);
+        let transform = sim.app.world().get::<Transform>(ship).unwrap();
+        assert!(transform.translation.x < 100.0);
+    }
+
+    #[test]
+    fn losing_the_last_life_ends_the_match() {
+        let mut sim = Harness::new();
+        let ship = sim.spawn_ship(1, Vec2::new(500.0, 500.0));
+        sim.app.world_mut().entity_mut(ship).insert(Lives(1));
+        sim.spawn_large_asteroid(Vec2::new(510.0, 500.0));
+        sim.step(10);
+
+        assert_eq!(sim.state(), GameState::GameOver);
+        let results = sim.app.world().resource::<MatchResults>();
+        assert_eq!(results.players.len(), 1);
+        assert_eq!(results.players[0].name, "Player 1");
+    }
+
+    /// Score and asteroid count after flying a fixed pattern through the field of `seed`.
+    fn seeded_run(seed: u64) -> (Option<Score>, usize) {
+        let mut sim = Harness::new();
+        sim.seed(seed);
+        let ship = sim.spawn_ship(1, Vec2::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0));
+        for tick in 0..FIXED_TIMESTEP_HZ as usize * 20 {
+            sim.input(
+                1,
+                ShipInput {
+                    thrust: (tick % 90 < 10) as i8,
+                    rotate: 1,
+                    shoot: tick % 40 == 0,
+                    ..default()
+                },
+            );
+            sim.step(1);
+        }
+        let score = sim.app.world().get::<Score>(ship).cloned();
+        (score, sim.asteroids().len())
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_out_the_same() {
+        assert_eq!(seeded_run(7), seeded_run(7));
+    }
+
+    #[test]
+    fn coop_goes_on_while_downed_ships_can_be_revived() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameMode::Coop);
+        let pool = sim.app.world_mut().spawn((TeamPool, Lives(3))).id();
+        // Let the first wave go by before anyone goes down
+        sim.step(1);
+        let ships = [
+            sim.spawn_ship(1, Vec2::new(300.0, 500.0)),
+            sim.spawn_ship(2, Vec2::new(700.0, 500.0)),
+        ];
+        for ship in ships {
+            sim.app.world_mut().trigger_targets(OnPlayerDamage, ship);
+        }
+        sim.step(2);
+
+        assert_eq!(sim.state(), GameState::Playing);
+        assert!(
+            ships
+                .iter()
+                .all(|ship| sim.app.world().get::<Downed>(*ship).is_some())
+        );
+
+        // Everyone gets back up on the next wave, for a life each
+        sim.app.world_mut().resource_mut::<MatchClock>().elapsed = Duration::from_secs(30);
+        sim.step(2);
+
+        assert_eq!(sim.state(), GameState::Playing);
+        assert!(
+            ships
+                .iter()
+                .all(|ship| sim.app.world().get::<Downed>(*ship).is_none())
+        );
+        assert_eq!(sim.app.world().get::<Lives>(pool), Some(&Lives(1)));
+    }
+
+    #[test]
+    fn coop_ends_once_the_pool_cannot_revive_anyone() {
+        let mut sim = Harness::new();
+        sim.app.insert_resource(GameMode::Coop);
+        let pool = sim.app.world_mut().spawn((TeamPool, Lives(1))).id();
+        // Let the first wave go by before anyone goes down
+        sim.step(1);
+        let first = sim.spawn_ship(1, Vec2::new(300.0, 500.0));
+        let second = sim.spawn_ship(2, Vec2::new(700.0, 500.0));
+        for ship in [first, second] {
+            sim.app.world_mut().trigger_targets(OnPlayerDamage, ship);
+        }
+        sim.step(2);
+
+        // The last life of the pool only brings one ship back
+        sim.app.world_mut().resource_mut::<MatchClock>().elapsed = Duration::from_secs(30);
+        sim.step(2);
+
+        assert_eq!(sim.state(), GameState::Playing);
+        assert_eq!(sim.app.world().get::<Lives>(pool), Some(&Lives(0)));
+        let revived = [first, second]
+            .into_iter()
+            .filter(|ship| sim.app.world().get::<Downed>(*ship).is_none())
+            .collect::<Vec<_>>();
+        assert_eq!(revived.len(), 1);
+
+        // Nobody is left to bring the team back
+        sim.app
+            .world_mut()
+            .entity_mut(revived[0])
+            .remove::<PlayerGrace>();
+        sim.app
+            .world_mut()
+            .trigger_targets(OnPlayerDamage, revived[0]);
+        sim.app.world_mut().flush();
+        assert!(sim.app.world().get::<Eliminated>(revived[0]).is_some());
+        sim.step(2);
+
+        assert_eq!(sim.state(), GameState::GameOver);
+    }
+}
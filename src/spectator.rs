@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use rust_i18n::t;
+
+use crate::player::{Eliminated, PlayerId, PlayerName};
+use crate::{CleanupOnGameOver, GameState, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                spectate_when_eliminated,
+                show_spectator_hud.run_if(resource_added::<Spectating>),
+                (cycle_target, follow_target, update_spectator_hud)
+                    .chain()
+                    .run_if(resource_exists::<Spectating>),
+            )
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(OnExit(GameState::Playing), stop_spectating);
+    }
+}
+
+/// [`PlayerId`] of the ship controlled from this instance.
+#[derive(Resource)]
+pub struct LocalPlayer(pub u64);
+
+/// Present while the local player has no ship and watches others instead.
+#[derive(Resource, Default)]
+pub struct Spectating {
+    target: Option<Entity>,
+}
+
+#[derive(Component)]
+struct SpectatorText;
+
+fn spectate_when_eliminated(
+    mut cmd: Commands,
+    local: Option<Res<LocalPlayer>>,
+    spectating: Option<Res<Spectating>>,
+    eliminated: Query<&PlayerId, Added<Eliminated>>,
+) {
+    let Some(local) = local else {
+        return;
+    };
+    if spectating.is_none() && eliminated.iter().any(|id| id.0 == local.0) {
+        cmd.insert_resource(Spectating::default());
+    }
+}
+
+fn cycle_target(
+    key: Res<ButtonInput<KeyCode>>,
+    mut spectating: ResMut<Spectating>,
+    players: Query<Entity, (With<PlayerName>, Without<Eliminated>)>,
+) {
+    let mut candidates = players.iter().collect::<Vec<_>>();
+    candidates.sort();
+    let current = spectating
+        .target
+        .and_then(|target| candidates.iter().position(|it| *it == target));
+    let next = match current {
+        Some(index) if key.just_pressed(KeyCode::Tab) => {
+            Some(candidates[(index + 1) % candidates.len()])
+        }
+        Some(index) => Some(candidates[index]),
+        None => candidates.first().copied(),
+    };
+    if spectating.target != next {
+        spectating.target = next;
+    }
+}
+
+fn follow_target(
+    spectating: Res<Spectating>,
+    mut camera: Single<&mut Transform, With<Camera2d>>,
+    targets: Query<&Transform, Without<Camera2d>>,
+) {
+    let position = spectating
+        .target
+        .and_then(|target| targets.get(target).ok())
+        .map(|it| it.translation.xy())
+        .unwrap_or(Vec2::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0));
+    camera.translation.x = position.x;
+    camera.translation.y = position.y;
+}
+
+fn show_spectator_hud(mut cmd: Commands) {
+    cmd.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::End,
+            justify_content: JustifyContent::Center,
+            padding: UiRect {
+                left: Val::Px(0.0),
+                right: Val::Px(0.0),
+                top: Val::Px(0.0),
+                bottom: Val::Px(10.0),
+            },
+            ..default()
+        },
+        CleanupOnGameOver,
+    ))
+    .with_child((Text::new(""), SpectatorText));
+}
+
+fn update_spectator_hud(
+    spectating: Res<Spectating>,
+    mut text: Single<&mut Text, With<SpectatorText>>,
+    names: Query<&PlayerName>,
+) {
+    text.0 = match spectating.target.and_then(|target| names.get(target).ok()) {
+        Some(name) => t!("spectating", name = name.0).to_string(),
+        None => t!("spectating.nobody").to_string(),
+    };
+}
+
+fn stop_spectating(mut cmd: Commands, mut camera: Single<&mut Transform, With<Camera2d>>) {
+    cmd.remove_resource::<Spectating>();
+    camera.translation.x = WINDOW_WIDTH / 2.0;
+    camera.translation.y = WINDOW_HEIGHT / 2.0;
+}
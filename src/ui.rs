@@ -9,50 +9,54 @@ use rust_i18n::t;
 use strum::IntoEnumIterator;
 
 use crate::{
-    CleanupOnRestart, GameState, HostGame, JoinGame, Language, Lives, OnScoreUpdate, Score,
-    ServerAddress, player::OnPlayerDamage,
+    CleanupOnRestart, GameState, HostGame, JoinGame, Language, Lives, Nickname, Score,
+    ServerAddress,
+    auth::SecuritySettings,
+    daily::Daily,
+    discovery::DiscoveredServers,
+    game_mode::{GameMode, format_clock, setup_mode_hud, update_mode_hud},
+    highscores::HighScores,
+    network_debug::{NetworkSettings, ShowNetworkDiagnostics},
+    player::{Downed, Eliminated, PlayerId, PlayerName, PlayerStats},
+    shared::{MatchResults, ProtocolVersion, RejectReason},
+    spectator::LocalPlayer,
+    weapon::Weapons,
 };
 
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (main_menu).run_if(in_state(GameState::MainMenu)))
-            .add_systems(OnEnter(GameState::GameOver), handle_gameover)
-            .add_systems(OnEnter(GameState::Playing), setup_hud)
-            .add_observer(update_score)
-            .add_observer(update_lives)
-            .init_resource::<EnableInspector>()
-            .add_plugins((
-                EguiPlugin,
-                bevy_inspector_egui::quick::WorldInspectorPlugin::default().run_if(
-                    resource_exists_and_equals::<EnableInspector>(EnableInspector(true)),
-                ),
-            ));
+        app.add_systems(
+            Update,
+            (
+                (main_menu).run_if(in_state(GameState::MainMenu)),
+                (update_scoreboard, update_mode_hud).run_if(in_state(GameState::Playing)),
+                (results_screen).run_if(in_state(GameState::GameOver)),
+            ),
+        )
+        .add_systems(OnEnter(GameState::Playing), (setup_hud, setup_mode_hud))
+        .init_resource::<EnableInspector>()
+        .add_plugins((
+            EguiPlugin,
+            bevy_inspector_egui::quick::WorldInspectorPlugin::default().run_if(
+                resource_exists_and_equals::<EnableInspector>(EnableInspector(true)),
+            ),
+        ));
     }
 }
 
 #[derive(Default, Resource, PartialEq)]
 struct EnableInspector(bool);
 
+/// Why the server refused the last connection attempt.
+#[derive(Resource)]
+pub struct Rejection(pub RejectReason);
+
+#[derive(Component)]
+struct Scoreboard;
+
 fn setup_hud(mut cmd: Commands) {
-    cmd.spawn((
-        Node {
-            width: Val::Percent(100.0),
-            height: Val::Percent(100.0),
-            align_items: AlignItems::Start,
-            justify_content: JustifyContent::Center,
-            padding: UiRect {
-                left: Val::Px(0.0),
-                right: Val::Px(0.0),
-                top: Val::Px(10.0),
-                bottom: Val::Px(0.0),
-            },
-            ..default()
-        },
-        CleanupOnRestart,
-    ))
-    .with_child((Text::new(t!("points", count = 0)), Score::default()));
     cmd.spawn((
         Node {
             width: Val::Percent(100.0),
@@ -69,31 +73,117 @@ fn setup_hud(mut cmd: Commands) {
         },
         CleanupOnRestart,
     ))
-    .with_child((Text::new("X X X"), Lives::default()));
+    .with_child((Text::new(""), Scoreboard));
 }
 
-fn handle_gameover(mut cmd: Commands) {
-    cmd.spawn((
-        Node {
-            width: Val::Percent(100.0),
-            height: Val::Percent(100.0),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            padding: UiRect {
-                left: Val::Px(0.0),
-                right: Val::Px(0.0),
-                top: Val::Px(0.0),
-                bottom: Val::Px(0.0),
-            },
-            flex_direction: FlexDirection::Column,
-            ..default()
-        },
-        CleanupOnRestart,
-    ))
-    .with_children(|parent| {
-        parent.spawn(Text::new(t!("gameover")));
-        parent.spawn(Text::new(t!("goto_main_menu")));
-    });
+fn update_scoreboard(
+    mut text: Single<&mut Text, With<Scoreboard>>,
+    players: Query<(
+        &PlayerName,
+        &Score,
+        &Lives,
+        &PlayerStats,
+        Has<Eliminated>,
+        Has<Downed>,
+    )>,
+    mode: Res<GameMode>,
+    local: Option<Res<LocalPlayer>>,
+    weapons: Query<(&PlayerId, &Weapons)>,
+) {
+    let mut players = players.iter().collect::<Vec<_>>();
+    players.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    text.0 = players
+        .iter()
+        .map(|(name, score, lives, stats, eliminated, downed)| {
+            // Co-op lives are shown as the team pool instead
+            let lives = match *mode {
+                GameMode::Coop => 0,
+                _ => lives.0.max(0) as usize,
+            };
+            t!(
+                "scoreboard.row",
+                name = name.0,
+                score = match *mode {
+                    GameMode::Survival => format_clock(stats.survived_secs as u64),
+                    _ => score.0.to_string(),
+                },
+                lives = "X ".repeat(lives),
+                status = if *eliminated {
+                    t!("status.out")
+                } else if *downed {
+                    t!("status.down")
+                } else {
+                    t!("status.alive")
+                }
+            )
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let weapon = weapons
+        .iter()
+        .find(|(id, _)| local.as_ref().is_some_and(|it| it.0 == id.0))
+        .map(|(_, weapons)| weapons.current());
+    if let Some(weapon) = weapon {
+        let line = t!(
+            "hud.weapon",
+            weapon = weapon.kind.name(),
+            ammo = weapon.ammo.map(|it| it.to_string()).unwrap_or_default()
+        );
+        text.0 = format!("{}\n\n{}", text.0, line);
+    }
+}
+
+fn results_screen(
+    mut ctx: EguiContexts,
+    results: Option<Res<MatchResults>>,
+    mode: Res<GameMode>,
+    high_scores: Res<HighScores>,
+) {
+    let rect = ctx.ctx_mut().input(|i: &egui::InputState| i.screen_rect());
+    egui::Window::new(t!("gameover"))
+        .pivot(Align2::CENTER_CENTER)
+        .current_pos(egui::Pos2::new(rect.max.x / 2.0, rect.max.y / 2.0))
+        .show(ctx.ctx_mut(), |ui| {
+            if let Some(results) = results {
+                if *mode == GameMode::Coop {
+                    let score = results.players.iter().map(|it| it.score).sum::<u32>();
+                    ui.heading(t!("mode.team_score", score = score));
+                }
+                egui::Grid::new("results").striped(true).show(ui, |ui| {
+                    ui.label("#");
+                    ui.label(t!("results.name"));
+                    ui.label(match *mode {
+                        GameMode::Survival => t!("results.survived"),
+                        _ => t!("results.score"),
+                    });
+                    ui.label(t!("results.accuracy"));
+                    ui.label(t!("results.destroyed"));
+                    ui.label(t!("results.kills"));
+                    ui.end_row();
+                    for (rank, player) in results.players.iter().enumerate() {
+                        ui.label((rank + 1).to_string());
+                        ui.label(&player.name);
+                        ui.label(mode.format_result(mode.result(player)));
+                        ui.label(format!("{:.0}%", player.stats.accuracy() * 100.0));
+                        ui.label(player.stats.asteroids_destroyed.to_string());
+                        ui.label(player.stats.kills.to_string());
+                        ui.end_row();
+                    }
+                });
+            }
+            ui.separator();
+            ui.label(t!("highscores", mode = mode.name()));
+            egui::Grid::new("highscores").striped(true).show(ui, |ui| {
+                for (rank, entry) in high_scores.top(*mode).enumerate() {
+                    ui.label((rank + 1).to_string());
+                    ui.label(&entry.name);
+                    ui.label(mode.format_result(entry.value));
+                    ui.end_row();
+                }
+            });
+            ui.label(t!("goto_main_menu"));
+        });
 }
 
 fn main_menu(
@@ -102,6 +192,15 @@ fn main_menu(
     mut lang: ResMut<Language>,
     mut inspector: ResMut<EnableInspector>,
     mut address: ResMut<ServerAddress>,
+    mut nickname: ResMut<Nickname>,
+    servers: Res<DiscoveredServers>,
+    protocol: Res<ProtocolVersion>,
+    rejection: Option<Res<Rejection>>,
+    mut security: ResMut<SecuritySettings>,
+    mut network: ResMut<NetworkSettings>,
+    mut show_network: ResMut<ShowNetworkDiagnostics>,
+    mut daily: ResMut<Daily>,
+    mut mode: ResMut<GameMode>,
 ) {
     let rect = ctx.ctx_mut().input(|i: &egui::InputState| i.screen_rect());
     egui::Window::new("Asteroids")
@@ -119,7 +218,16 @@ fn main_menu(
                         );
                     });
                 });
+            if let Some(rejection) = rejection {
+                ui.colored_label(egui::Color32::RED, rejection.0.describe());
+            }
             ui.checkbox(&mut inspector.0, t!("inspector"));
+            ui.checkbox(&mut show_network.0, t!("netstats.show"));
+            ui.collapsing(t!("netsim"), |ui| network.edit(ui));
+            ui.horizontal(|ui| {
+                ui.label(t!("nickname"));
+                ui.text_edit_singleline(&mut nickname.0);
+            });
             ui.horizontal(|ui| {
                 let mut text = address.ip.clone();
                 let mut port = address.port.clone().to_string();
@@ -132,28 +240,68 @@ fn main_menu(
                     }
                 }
             });
+            ui.horizontal(|ui| {
+                ui.label(t!("password"));
+                ui.add(egui::TextEdit::singleline(&mut security.password).password(true));
+            });
+            ui.checkbox(&mut security.require_token, t!("host.require_token"));
+            ui.checkbox(&mut security.kick_cheaters, t!("host.kick_cheaters"));
             ui.horizontal(|ui| {
                 if ui.button(t!("play.host")).clicked() {
-                    cmd.trigger(HostGame);
+                    cmd.trigger(HostGame::default());
                 }
                 if ui.button(t!("play.join")).clicked() {
                     cmd.trigger(JoinGame);
                 }
             });
+            ui.separator();
+            ui.label(t!("daily", date = daily.date));
+            ui.label(
+                daily
+                    .modifiers
+                    .iter()
+                    .map(|it| it.name())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+            if daily.record.attempts > 0 {
+                ui.label(t!(
+                    "daily.best",
+                    score = daily.record.best,
+                    attempts = daily.record.attempts
+                ));
+            }
+            if ui.button(t!("daily.play")).clicked() {
+                daily.active = true;
+                *mode = GameMode::Endless;
+                cmd.trigger(HostGame { local: true });
+            }
+            ui.separator();
+            ui.label(t!("servers.lan"));
+            if servers.servers.is_empty() {
+                ui.label(t!("servers.searching"));
+            }
+            egui::Grid::new("servers").striped(true).show(ui, |ui| {
+                for server in &servers.servers {
+                    let announcement = &server.announcement;
+                    ui.label(&announcement.name);
+                    ui.label(server.address.to_string());
+                    ui.label(t!("servers.players", count = announcement.players));
+                    ui.label(&announcement.state);
+                    ui.label(format!("{} ms", server.ping.as_millis()));
+                    let compatible = announcement.version == protocol.0;
+                    if ui
+                        .add_enabled(compatible, egui::Button::new(t!("play.join")))
+                        .on_disabled_hover_text(t!("servers.incompatible"))
+                        .clicked()
+                    {
+                        address.ip = server.address.ip().to_string();
+                        address.port = server.address.port();
+                        cmd.trigger(JoinGame);
+                    }
+                    ui.end_row();
+                }
+            });
         });
     rust_i18n::set_locale(lang.locale());
 }
-
-fn update_lives(_event: Trigger<OnPlayerDamage>, mut text: Query<(&mut Text, &mut Lives)>) {
-    text.iter_mut().for_each(|(mut text, mut lives)| {
-        lives.0 -= 1;
-        text.0 = "X ".repeat(lives.0 as usize);
-    });
-}
-
-fn update_score(event: Trigger<OnScoreUpdate>, mut text: Query<(&mut Text, &mut Score)>) {
-    text.iter_mut().for_each(|(mut text, mut score)| {
-        score.0 += event.0;
-        text.0 = t!("points", count = score.0.to_string()).to_string();
-    });
-}
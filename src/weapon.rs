@@ -0,0 +1,240 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+use crate::game_mode::GameMode;
+use crate::player::{Owner, PlayerId, ScoreMarker};
+use crate::rules::GameRules;
+use crate::simulation::Lifetime;
+use crate::{CircleCollider, CleanupOnGameOver, CollisionLayers, NNTree, Velocity};
+
+/// Longest reach of a laser beam, in pixels.
+pub const LASER_RANGE: f32 = 600.0;
+pub const LASER_WIDTH: f32 = 4.0;
+/// Seconds a beam stays on screen after it was fired.
+const BEAM_DURATION: f32 = 0.15;
+/// Share of the ship's velocity a dropped mine keeps.
+const MINE_DRIFT: f32 = 0.3;
+const MINE_LIFETIME: f32 = 10.0;
+/// Speed of a missile relative to a standard projectile.
+const MISSILE_SPEED: f32 = 0.6;
+const MISSILE_LIFETIME: f32 = 3.0;
+/// Distance within which a missile locks onto a target.
+const HOMING_RADIUS: f32 = 300.0;
+/// Largest turn rate of a missile, in radians per second.
+const HOMING_TURN_RATE: f32 = PI;
+
+/// What a ship fires. Projectiles carry the kind that fired them, so clients can draw them.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default, EnumIter, Serialize, Deserialize,
+)]
+pub enum WeaponKind {
+    #[default]
+    Standard,
+    /// Hits the first thing along the ship's facing right away.
+    Laser,
+    /// Drifts slowly until something runs into it.
+    Mine,
+    /// Steers towards the nearest target.
+    Missile,
+}
+
+impl WeaponKind {
+    pub fn name(&self) -> String {
+        match self {
+            WeaponKind::Standard => t!("weapon.standard").to_string(),
+            WeaponKind::Laser => t!("weapon.laser").to_string(),
+            WeaponKind::Mine => t!("weapon.mine").to_string(),
+            WeaponKind::Missile => t!("weapon.missile").to_string(),
+        }
+    }
+
+    /// Seconds before the ship may fire again after a shot of this weapon.
+    pub fn cooldown(&self, rules: &GameRules) -> f32 {
+        match self {
+            WeaponKind::Standard | WeaponKind::Mine => rules.shoot_timeout,
+            WeaponKind::Laser | WeaponKind::Missile => rules.shoot_timeout * 2.0,
+        }
+    }
+
+    /// Shots a ship starts the match with, unlimited for the standard gun.
+    pub fn ammo(&self) -> Option<u32> {
+        match self {
+            WeaponKind::Standard => None,
+            WeaponKind::Laser => Some(8),
+            WeaponKind::Mine => Some(6),
+            WeaponKind::Missile => Some(6),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub ammo: Option<u32>,
+}
+
+/// Weapons a ship carries, the standard gun always comes first.
+#[derive(Component, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Weapons {
+    slots: Vec<Weapon>,
+    selected: usize,
+    /// The switch was held on the last input, ships switch once per press.
+    #[serde(skip)]
+    switch_held: bool,
+}
+
+impl Weapons {
+    /// Standard gun and the weapons picked in the lobby, fully loaded.
+    pub fn new(loadout: &[WeaponKind]) -> Self {
+        let slots = std::iter::once(WeaponKind::Standard)
+            .chain(loadout.iter().copied())
+            .fold(Vec::<Weapon>::new(), |mut slots, kind| {
+                if !slots.iter().any(|it| it.kind == kind) {
+                    slots.push(Weapon {
+                        kind,
+                        ammo: kind.ammo(),
+                    });
+                }
+                slots
+            });
+        Self {
+            slots,
+            selected: 0,
+            switch_held: false,
+        }
+    }
+
+    pub fn current(&self) -> Weapon {
+        self.slots[self.selected]
+    }
+
+    /// Switches to the next weapon when the switch gets pressed.
+    pub fn hold_switch(&mut self, held: bool) {
+        if held && !self.switch_held {
+            self.selected = (self.selected + 1) % self.slots.len();
+        }
+        self.switch_held = held;
+    }
+
+    /// Uses up a shot of the current weapon, going back to the standard gun once it is empty.
+    pub fn spend(&mut self) {
+        let Some(ammo) = &mut self.slots[self.selected].ammo else {
+            return;
+        };
+        *ammo = ammo.saturating_sub(1);
+        if *ammo == 0 {
+            self.slots.remove(self.selected);
+            self.selected = 0;
+        }
+    }
+}
+
+/// First collider a laser fired from `origin` along `direction` runs into, with its distance.
+pub fn laser_target<'a>(
+    origin: Vec2,
+    direction: Vec2,
+    targets: impl Iterator<Item = (Entity, Vec2, &'a CircleCollider)>,
+) -> Option<(Entity, CollisionLayers, f32)> {
+    let beam = CircleCollider::projectile(LASER_WIDTH / 2.0);
+    targets
+        .filter(|(_, _, collider)| beam.collides_with(collider))
+        .filter_map(|(entity, center, collider)| {
+            let along = (center - origin).dot(direction);
+            let off = (center - origin - direction * along).length();
+            ((0.0..=LASER_RANGE).contains(&along) && off <= collider.radius + beam.radius)
+                .then_some((entity, collider.layer, along))
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+/// Beam of `length` pixels fired by `owner`.
+/// The hit itself is decided when firing, so beams collide with nothing.
+pub fn beam(transform: &Transform, length: f32, owner: PlayerId) -> impl Bundle {
+    let direction = transform.rotation * Vec3::Y;
+    (
+        Transform::from_translation(transform.translation + direction * length / 2.0)
+            .with_rotation(transform.rotation)
+            .with_scale(Vec3::new(1.0, length, 1.0)),
+        Velocity { x: 0.0, y: 0.0 },
+        Lifetime::Time(Timer::from_seconds(BEAM_DURATION, TimerMode::Once)),
+        ScoreMarker,
+        Owner(owner),
+        WeaponKind::Laser,
+        CleanupOnGameOver,
+    )
+}
+
+/// Replaces the components of a standard projectile to leave a mine behind the ship.
+pub fn mine(velocity: &Velocity, rules: &GameRules) -> impl Bundle {
+    (
+        Velocity {
+            x: velocity.x * MINE_DRIFT,
+            y: velocity.y * MINE_DRIFT,
+        },
+        Lifetime::Time(Timer::from_seconds(MINE_LIFETIME, TimerMode::Once)),
+        CircleCollider::projectile(rules.projectile_radius * 1.5),
+        WeaponKind::Mine,
+    )
+}
+
+/// Replaces the components of a standard projectile to launch a slower, homing missile.
+pub fn missile(transform: &Transform, velocity: &Velocity, rules: &GameRules) -> impl Bundle {
+    let direction = transform.rotation * Vec3::Y;
+    let speed = rules.projectile_speed * MISSILE_SPEED;
+    (
+        Transform::from_translation(transform.translation).with_rotation(transform.rotation),
+        Velocity {
+            x: velocity.x + direction.x * speed,
+            y: velocity.y + direction.y * speed,
+        },
+        Lifetime::Time(Timer::from_seconds(MISSILE_LIFETIME, TimerMode::Once)),
+        WeaponKind::Missile,
+    )
+}
+
+/// Turns missiles towards the nearest asteroid, or the nearest enemy ship in versus.
+pub fn steer_missiles(
+    mut missiles: Query<(&mut Transform, &mut Velocity, &Owner, &WeaponKind)>,
+    targets: Query<(&CircleCollider, Option<&PlayerId>)>,
+    tree: Res<NNTree>,
+    mode: Res<GameMode>,
+    time: Res<Time>,
+) {
+    let max_turn = HOMING_TURN_RATE * time.delta_secs();
+    for (mut transform, mut velocity, owner, kind) in &mut missiles {
+        if *kind != WeaponKind::Missile {
+            continue;
+        }
+        let position = transform.translation.xy();
+        let is_target = |entity: Entity| {
+            targets.get(entity).is_ok_and(|(collider, id)| {
+                collider.layer == CollisionLayers::ASTEROID
+                    || (*mode == GameMode::Versus
+                        && collider.layer == CollisionLayers::SHIP
+                        && id.is_some_and(|it| *it != owner.0))
+            })
+        };
+        let target = tree
+            .within_distance(position, HOMING_RADIUS)
+            .into_iter()
+            .filter(|(_, entity)| entity.is_some_and(&is_target))
+            .map(|(pos, _)| pos)
+            .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)));
+        let Some(target) = target else {
+            continue;
+        };
+        let heading = Vec2::new(velocity.x, velocity.y);
+        let turn = heading
+            .angle_to(target - position)
+            .clamp(-max_turn, max_turn);
+        let steered = Vec2::from_angle(turn).rotate(heading);
+        velocity.x = steered.x;
+        velocity.y = steered.y;
+        transform.rotation = Quat::from_rotation_z(Vec2::Y.angle_to(steered));
+    }
+}